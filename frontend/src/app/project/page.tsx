@@ -63,6 +63,7 @@ interface Project {
   last_active: string;
   status: string;
   memory: Array<{
+    id: number;
     memtype: string;
     content: string;
     proof: string;
@@ -83,6 +84,7 @@ interface Project {
 }
 
 interface MemoryBlock {
+  id: number;
   memtype: string;
   content: string;
   proof: string;
@@ -114,9 +116,31 @@ interface ProjectConfig {
   theorem_graph_mode: boolean;
 }
 
+// Counterexamples carry the disproof of their statement, failed attempts a rejected proof
+function proofEnv(memtype: string): string {
+  switch (memtype) {
+    case 'counterexample': return 'disproof';
+    case 'failed_attempt': return 'failed_proof';
+    default: return 'proof';
+  }
+}
+
+function lemmaStatus(memory: MemoryBlock): Lemma['status'] {
+  switch (memory.memtype) {
+    case 'counterexample':
+    case 'failed_attempt':
+      return 'invalid';
+    case 'open_question':
+      return 'pending';
+    default:
+      return memory.solved ? 'proved' : 'pending';
+  }
+}
+
 function formatMemoryBlockToMarkdown(id: number, memory: MemoryBlock): string {
+  const env = proofEnv(memory.memtype);
   const proofContent = memory.proof.trim().length > 0
-    ? `\n\\begin{proof}\n${memory.proof}\n\\end{proof}`
+    ? `\n\\begin{${env}}\n${memory.proof}\n\\end{${env}}`
     : '';
   return `#### Memory **ID: ${id}**\n\n\\begin{${memory.memtype}}\n${memory.content}\n\n**DEPENDENCY**: ${JSON.stringify(memory.deps)}\n\\end{${memory.memtype}}${proofContent}\n`;
 }
@@ -126,10 +150,11 @@ function buildProofPath(memory: MemoryBlock[]): ProofPathSummary {
     return { finalTheoremId: null, pathIds: [], markdown: '' };
   }
 
+  const byId = new Map<number, MemoryBlock>(memory.map((m) => [m.id, m]));
   let finalTheoremId: number | null = null;
   for (let i = memory.length - 1; i >= 0; i -= 1) {
     if (memory[i].memtype === 'theorem') {
-      finalTheoremId = i;
+      finalTheoremId = memory[i].id;
       break;
     }
   }
@@ -142,10 +167,10 @@ function buildProofPath(memory: MemoryBlock[]): ProofPathSummary {
   while (queue.length > 0) {
     const currentId = queue.shift();
     if (currentId === undefined) break;
-    const block = memory[currentId];
+    const block = byId.get(currentId);
     if (!block) continue;
     block.deps.forEach((depId) => {
-      if (!required.has(depId) && memory[depId]) {
+      if (!required.has(depId) && byId.has(depId)) {
         required.add(depId);
         queue.push(depId);
       }
@@ -153,11 +178,13 @@ function buildProofPath(memory: MemoryBlock[]): ProofPathSummary {
   }
 
   if (memory[0]?.memtype === 'context') {
-    required.add(0);
+    required.add(memory[0].id);
   }
 
   const pathIds = Array.from(required).sort((a, b) => a - b);
-  const markdown = pathIds.map((id) => formatMemoryBlockToMarkdown(id, memory[id])).join('\n');
+  const markdown = pathIds
+    .map((id) => formatMemoryBlockToMarkdown(id, byId.get(id) as MemoryBlock))
+    .join('\n');
   return { finalTheoremId, pathIds, markdown };
 }
 
@@ -206,12 +233,13 @@ const ProjectDetailContent: React.FC = () => {
         const data: Project = await res.json();
         setProject(data);
         // map memory to lemmas
-        const mapped = data.memory.map((m, idx) => ({
-          id: idx,
-          title: `${m.memtype}-${idx}`,
+        const mapped = data.memory.map((m) => ({
+          id: m.id,
+          title: `${m.memtype}-${m.id}`,
+          kind: m.memtype,
           statement: m.content,
           proof: m.proof,
-          status: m.solved ? 'proved' : 'pending',
+          status: lemmaStatus(m),
           createdAt: formatDateTime(m.created_at),
           lastUpdated: formatDateTime(m.updated_at),
           reviews: m.reviews,
@@ -455,7 +483,7 @@ const ProjectDetailContent: React.FC = () => {
               ) : (
                 <div className="space-y-4">
                   {proofPath.pathIds.map((id, index) => {
-                    const mem = project.memory[id];
+                    const mem = project.memory.find((m) => m.id === id);
                     if (!mem) return null;
                     return (
                       <div key={`proof-path-${id}`} className="rounded-lg border border-gray-200 bg-white p-4">
@@ -216,7 +216,8 @@ const LemmaDetail: React.FC<LemmaDetailProps> = ({ lemma }) => {
       {/* 引理证明内容：展示完整证明，无滚动 */}
       <CopyableBlock text={lemma.proof}>
         <div className="p-6">
-          <h3 className="text-xl font-bold mb-4 text-blue-800">{t('proof')}</h3>
+          <h3 className="text-xl font-bold mb-4 text-blue-800">{lemma.kind === 'counterexample' ? t('disproof') :
+            lemma.kind === 'failed_attempt' ? t('failed_proof') : t('proof')}</h3>
           <div className="prose max-w-none text-gray-700">
             {renderProofContent()}
           </div>
@@ -116,6 +116,8 @@ const en: Dict = {
   none: 'None',
   lemma_statement: 'Lemma Statement:',
   proof: 'Proof:',
+  disproof: 'Disproof:',
+  failed_proof: 'Rejected Proof:',
   review_comment: 'Review Comment:',
   created_at_label: 'Created at:',
   last_updated_label: 'Last updated:',
@@ -258,6 +260,8 @@ const zh: Dict = {
   none: '无',
   lemma_statement: '引理陈述:',
   proof: '证明:',
+  disproof: '反证:',
+  failed_proof: '未通过的证明:',
   review_comment: '评审评论:',
   created_at_label: '创建时间:',
   last_updated_label: '最后更新:',
@@ -1,6 +1,8 @@
 interface Lemma {
   id: number;
   title: string;
+  // memory block kind, e.g. lemma, counterexample, failed_attempt
+  kind: string;
   statement: string;
   proof: string;
   status: 'pending' | 'in_progress' | 'proved' | 'invalid';
@@ -2,20 +2,38 @@ use futures_util::StreamExt;
 use log::{debug, error, info, warn};
 use serde_json::json;
 use tokio::task::JoinSet;
+use tracing::{Instrument, info_span};
 
-use crate::utils::find_box;
+use crate::events::JsonlLog;
+use crate::utils::{
+    canonical_text, content_hash, estimate_tokens, extract_component, find_box, normalize_text,
+    text_similarity, truncate_chars,
+};
 use dotenvy::dotenv;
 use indicatif::{ProgressBar, ProgressStyle};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::env;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 // const CONNECT_TIMEOUT: Duration = Duration::from_secs(6000);
 // const REQUEST_TIMEOUT: Duration = Duration::from_secs(18000);
 const API_RETRY_DELAY: Duration = Duration::from_secs(2);
 const MAX_REQWEST_RETRIES: u8 = 7;
 const MAX_CHUNK_DECODE_RETRIES: u8 = 16;
+// Sampling temperature of every completion request
+const TEMPERATURE: f64 = 1.0;
+// Bounds of the known dead ends section shown to the explorer
+const MAX_DEAD_ENDS: usize = 12;
+const MAX_DEAD_END_CHARS: usize = 1600;
+// Weights of the relevance signals used when assembling a budgeted context
+// Recency is scored by position among the candidate blocks, which are kept in insertion order
+const DEPENDENCY_WEIGHT: f64 = 0.4;
+const OVERLAP_WEIGHT: f64 = 0.3;
+const RECENCY_WEIGHT: f64 = 0.15;
+const USAGE_WEIGHT: f64 = 0.15;
 
 use chrono::{DateTime, Utc};
 
@@ -24,9 +42,107 @@ pub fn default_datetime() -> DateTime<Utc> {
     Utc::now()
 }
 
+/// The kind of a memory block. Serialized in snake case, which keeps memory files written with
+/// the former free-form `memtype` strings ("context", "lemma", "theorem") readable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemType {
+    /// Background of the problem, accepted without review
+    Context,
+    /// A proved intermediate result
+    #[default]
+    Lemma,
+    /// The proof of the original problem
+    Theorem,
+    /// A new notion introduced during exploration, checked for well-formedness
+    Definition,
+    /// A conjecture shown to be false, together with the counterexample or disproof
+    #[serde(alias = "refuted_conjecture")]
+    Counterexample,
+    /// An approach that did not survive review, kept to avoid repeating it
+    FailedAttempt,
+    /// A known result from the literature, accepted with its reference
+    ExternalCitation,
+    /// A statement that is neither proved nor refuted yet
+    OpenQuestion,
+    /// A consolidated summary of the research state, read by the explorer in place of the lemmas
+    Digest,
+}
+
+impl MemType {
+    /// Name of the latex environment used when formatting memory blocks of this kind
+    pub fn env(&self) -> &'static str {
+        match self {
+            MemType::Context => "context",
+            MemType::Lemma => "lemma",
+            MemType::Theorem => "theorem",
+            MemType::Definition => "definition",
+            MemType::Counterexample => "counterexample",
+            MemType::FailedAttempt => "failed_attempt",
+            MemType::ExternalCitation => "external_citation",
+            MemType::OpenQuestion => "open_question",
+            MemType::Digest => "digest",
+        }
+    }
+    /// Name of the latex environment of the proof, which disproves a counterexample's statement
+    pub fn proof_env(&self) -> &'static str {
+        match self {
+            MemType::Counterexample => "disproof",
+            MemType::FailedAttempt => "failed_proof",
+            _ => "proof",
+        }
+    }
+    /// What a memory block of this kind states, told to agents rewriting it
+    pub fn describe(&self) -> &'static str {
+        match self {
+            MemType::Context => "background context of the problem",
+            MemType::Lemma => "a lemma",
+            MemType::Theorem => "a theorem",
+            MemType::Definition => "a definition",
+            MemType::Counterexample => "a conjecture that was refuted",
+            MemType::FailedAttempt => "an attempt that failed to pass review",
+            MemType::ExternalCitation => "a known result from the literature",
+            MemType::OpenQuestion => "an open question",
+            MemType::Digest => "a summary of the research state",
+        }
+    }
+    /// Whether memory blocks of this kind are checked by reviewers. Counterexamples come with
+    /// the disproof that refuted them, not with a proof of their statement.
+    pub fn needs_review(&self) -> bool {
+        matches!(
+            self,
+            MemType::Lemma | MemType::Theorem | MemType::Definition
+        )
+    }
+    /// Whether memory blocks of this kind carry a proof
+    pub fn has_proof(&self) -> bool {
+        matches!(
+            self,
+            MemType::Lemma | MemType::Theorem | MemType::Counterexample | MemType::FailedAttempt
+        )
+    }
+    /// Whether memory blocks of this kind can be cited as dependencies of new results. The
+    /// statement of a counterexample is false, so it is shown to the explorer as a dead end instead.
+    pub fn is_citable(&self) -> bool {
+        matches!(
+            self,
+            MemType::Lemma | MemType::Definition | MemType::ExternalCitation
+        )
+    }
+}
+
+impl std::fmt::Display for MemType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.env())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MemoryBlock {
-    pub memtype: String,
+    /// Stable id of this memory block, assigned when it is inserted into a memory
+    #[serde(default = "unassigned_id")]
+    id: MemoryId,
+    pub memtype: MemType,
     pub content: String,
     pub proof: String,
     pub proof_summary: String,
@@ -40,7 +156,13 @@ pub struct MemoryBlock {
     solved: bool,
     reviews: u8,
     comment: String,
-    deps: Vec<usize>,
+    deps: Vec<MemoryId>,
+    /// Content hash of this block at the time it last passed a review, empty if never verified
+    #[serde(default)]
+    verified_hash: String,
+    /// Number of times explorations restated this block and were merged into it
+    #[serde(default)]
+    citations: u32,
 }
 
 impl MemoryBlock {
@@ -48,7 +170,8 @@ impl MemoryBlock {
     pub fn new() -> Self {
         let now = Utc::now();
         MemoryBlock {
-            memtype: String::new(),
+            id: UNASSIGNED_ID,
+            memtype: MemType::default(),
             content: String::new(),
             proof: String::new(),
             proof_summary: String::new(),
@@ -58,10 +181,12 @@ impl MemoryBlock {
             reviews: 0,
             comment: String::new(),
             deps: Vec::new(),
+            verified_hash: String::new(),
+            citations: 0,
         }
     }
-    pub fn memtype(mut self, memtype: impl Into<String>) -> Self {
-        self.memtype = memtype.into();
+    pub fn memtype(mut self, memtype: MemType) -> Self {
+        self.memtype = memtype;
         self.updated_at = Utc::now();
         self
     }
@@ -90,11 +215,19 @@ impl MemoryBlock {
         self.updated_at = Utc::now();
         self
     }
-    pub fn deps(mut self, deps: Vec<usize>) -> Self {
+    pub fn deps(mut self, deps: Vec<MemoryId>) -> Self {
+        self.deps = deps;
+        self.updated_at = Utc::now();
+        self
+    }
+    pub fn set_deps(&mut self, deps: Vec<MemoryId>) -> &Self {
         self.deps = deps;
         self.updated_at = Utc::now();
         self
     }
+    pub fn get_id(&self) -> MemoryId {
+        self.id
+    }
     pub fn is_solved(&self) -> bool {
         self.solved
     }
@@ -111,6 +244,19 @@ impl MemoryBlock {
         self.updated_at = Utc::now();
         self
     }
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = comment.into();
+        self.updated_at = Utc::now();
+        self
+    }
+    pub fn get_citations(&self) -> u32 {
+        self.citations
+    }
+    pub fn cite(&mut self) -> &Self {
+        self.citations = self.citations.saturating_add(1);
+        self.updated_at = Utc::now();
+        self
+    }
     pub fn get_comment(&self) -> &str {
         &self.comment
     }
@@ -119,26 +265,62 @@ impl MemoryBlock {
         self.updated_at = Utc::now();
         self
     }
+    pub fn set_verified_hash(&mut self, verified_hash: impl Into<String>) -> &Self {
+        self.verified_hash = verified_hash.into();
+        self.updated_at = Utc::now();
+        self
+    }
+    /// Mark this memory block as unsolved and reset its review counter so that it will be
+    /// reviewed again in the next backtrace review
+    pub fn invalidate(&mut self) -> &Self {
+        self.solved = false;
+        self.reviews = 0;
+        self.updated_at = Utc::now();
+        self
+    }
+
+    fn _format_statement(&self) -> String {
+        // Statement part of this memory block, shared by all the formatting variants
+        let env = self.memtype.env();
+        match self.memtype {
+            MemType::Context
+            | MemType::OpenQuestion
+            | MemType::ExternalCitation
+            | MemType::Digest => {
+                format!("\\begin{{{0}}}\n{1}\n\\end{{{0}}}", env, &self.content)
+            }
+            MemType::FailedAttempt => format!(
+                "\\begin{{{0}}}\n{1}\n\n**DEPENDENCY**: {2:?}\n\n**REASON OF FAILURE**: {3}\n\\end{{{0}}}",
+                env, &self.content, &self.deps, &self.comment
+            ),
+            _ => format!(
+                "\\begin{{{0}}}\n{1}\n\n**DEPENDENCY**: {2:?}\n\\end{{{0}}}",
+                env, &self.content, &self.deps
+            ),
+        }
+    }
 
     pub fn _format(&self) -> String {
-        format!(
-            "\\begin{{{0}}}\n{1}\n\n**DEPENDENCY**: {2:?}\n\\end{{{0}}}",
-            &self.memtype, &self.content, &self.deps
-        )
+        self._format_statement()
     }
     pub fn _format_with_proof(&self) -> String {
-        let proof_content = if self.proof.is_empty() {
+        let proof_content = if self.proof.is_empty() || !self.memtype.has_proof() {
             String::new()
         } else {
-            format!("\n\\begin{{proof}}\n{0}\n\\end{{proof}}", &self.proof)
+            format!(
+                "\n\\begin{{{0}}}\n{1}\n\\end{{{0}}}",
+                self.memtype.proof_env(),
+                &self.proof
+            )
         };
-        format!(
-            "\\begin{{{0}}}\n{1}\n\n**DEPENDENCY**: {2:?}\n\\end{{{0}}}{3}",
-            &self.memtype, &self.content, &self.deps, &proof_content
-        )
+        format!("{}{}", self._format_statement(), &proof_content)
     }
 
     pub fn _format_with_proof_summary(&self) -> String {
+        // Only reviewed results come with a proof sketch
+        if !(self.memtype.has_proof() && self.memtype.needs_review()) {
+            return self._format_statement();
+        }
         let ps_content = if self.proof_summary.is_empty() {
             String::new()
         } else {
@@ -148,50 +330,327 @@ impl MemoryBlock {
             )
         };
         format!(
-            "\\begin{{{0}}}\n{1}\n\n**DEPENDENCY**: {2:?}\n\\end{{{0}}}\n\n**Proof Sketch**{3}",
-            &self.memtype, &self.content, &self.deps, &ps_content
+            "{}\n\n**Proof Sketch**{}",
+            self._format_statement(),
+            &ps_content
         )
     }
 }
 
+/// Stable identifier of a memory block, never reused within one memory
+pub type MemoryId = usize;
+
+/// Placeholder id of memory blocks that have not been inserted into a memory yet
+const UNASSIGNED_ID: MemoryId = MemoryId::MAX;
+
+fn unassigned_id() -> MemoryId {
+    UNASSIGNED_ID
+}
+
 #[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(from = "MemoryRecord")]
 pub struct Memory {
     pub memory: Vec<MemoryBlock>,
+    /// The id assigned to the next inserted memory block
+    next_id: MemoryId,
+}
+
+/// Memory blocks chosen for an agent context under a token budget
+#[derive(Debug, Default)]
+pub struct ContextSelection {
+    pub text: Option<String>,
+    /// Included blocks with their estimated tokens, in memory order
+    pub included: Vec<(MemoryId, usize)>,
+    /// Blocks left out because they did not fit, by decreasing relevance
+    pub omitted: Vec<(MemoryId, usize)>,
+}
+
+impl ContextSelection {
+    pub fn tokens(&self) -> usize {
+        self.included.iter().map(|(_, t)| t).sum()
+    }
+    pub fn report(&self) -> String {
+        let fmt_ids = |blocks: &[(MemoryId, usize)]| {
+            blocks
+                .iter()
+                .map(|(id, tokens)| format!("{} (~{} tokens)", id, tokens))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!(
+            "included {} blocks (~{} tokens): [{}]; omitted {} blocks: [{}]",
+            self.included.len(),
+            self.tokens(),
+            fmt_ids(&self.included),
+            self.omitted.len(),
+            fmt_ids(&self.omitted)
+        )
+    }
+}
+
+/// Serialized form of `Memory`. Older memory files do not carry ids, which are then assigned
+/// from the positions of the blocks so that existing dependencies keep their meaning.
+#[derive(Deserialize)]
+struct MemoryRecord {
+    memory: Vec<MemoryBlock>,
+    #[serde(default)]
+    next_id: MemoryId,
+}
+
+impl From<MemoryRecord> for Memory {
+    fn from(record: MemoryRecord) -> Self {
+        let mut memory = Memory {
+            memory: record.memory,
+            next_id: record.next_id,
+        };
+        memory.assign_missing_ids();
+        memory
+    }
 }
 
 impl Memory {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn update(&mut self, nmemory: MemoryBlock) {
+
+    fn assign_missing_ids(&mut self) {
+        let mut assigned = 0;
+        for (pos, memblock) in self.memory.iter_mut().enumerate() {
+            if memblock.id == UNASSIGNED_ID {
+                memblock.id = pos;
+                assigned += 1;
+            }
+        }
+        if assigned > 0 {
+            info!(
+                "Assigned ids to {} memory blocks from legacy memory",
+                assigned
+            );
+        }
+        let max_id = self.memory.iter().map(|m| m.id + 1).max().unwrap_or(0);
+        self.next_id = self.next_id.max(max_id);
+    }
+
+    pub fn update(&mut self, mut nmemory: MemoryBlock) -> MemoryId {
+        let id = self.next_id;
+        self.next_id += 1;
+        nmemory.id = id;
         self.memory.push(nmemory);
+        id
+    }
+
+    pub fn record_dead_end(&mut self, nmemory: MemoryBlock) -> MemoryId {
+        // Keep a refuted conjecture or failed attempt, merging it into an existing dead end
+        // that repeats it: a counterexample with the same statement, or a failed attempt with the
+        // same statement and proof. Failed proofs of the problem all share its statement.
+        let key = |m: &MemoryBlock| match m.memtype {
+            MemType::FailedAttempt => (normalize_text(&m.content), normalize_text(&m.proof)),
+            _ => (normalize_text(&m.content), String::new()),
+        };
+        let nkey = key(&nmemory);
+        if let Some(existing) = self
+            .memory
+            .iter_mut()
+            .find(|m| m.memtype == nmemory.memtype && key(m) == nkey)
+        {
+            debug!("Merged a repeated dead end into memory ID {}", existing.id);
+            existing.proof = nmemory.proof;
+            existing.set_comment(nmemory.comment);
+            return existing.id;
+        }
+        self.update(nmemory.solved(false))
+    }
+
+    pub fn format_dead_ends(&self) -> Option<String> {
+        // Format the most recent refuted conjectures and failed attempts for the explorer
+        let mut dead_ends: Vec<&MemoryBlock> = self
+            .memory
+            .iter()
+            .filter(|m| matches!(m.memtype, MemType::Counterexample | MemType::FailedAttempt))
+            .filter(|m| !m.is_solved())
+            .collect();
+        if dead_ends.is_empty() {
+            return None;
+        }
+        dead_ends.sort_by_key(|m| std::cmp::Reverse(m.updated_at));
+        Some(
+            dead_ends
+                .iter()
+                .take(MAX_DEAD_ENDS)
+                .map(|m| {
+                    let body = match m.memtype {
+                        MemType::Counterexample => format!(
+                            "{}\n\n**Disproof**: {}",
+                            m._format_statement(),
+                            truncate_chars(&m.proof, MAX_DEAD_END_CHARS)
+                        ),
+                        _ => m._format_statement(),
+                    };
+                    format!(
+                        "#### Dead End **ID: {}**\n\n{}\n\n",
+                        m.id,
+                        truncate_chars(&body, 2 * MAX_DEAD_END_CHARS)
+                    )
+                })
+                .collect::<String>(),
+        )
+    }
+
+    pub fn dedup_candidates(&self, nmemory: &MemoryBlock) -> Vec<MemoryId> {
+        // Solved blocks of the same kind that a new block could restate
+        if !matches!(nmemory.memtype, MemType::Lemma | MemType::Definition) {
+            return Vec::new();
+        }
+        self.memory
+            .iter()
+            .filter(|m| m.memtype == nmemory.memtype && m.is_solved())
+            .map(|m| m.id)
+            .collect()
+    }
+
+    pub fn find_duplicate(&self, nmemory: &MemoryBlock, threshold: f64) -> Option<MemoryId> {
+        // A candidate with the same canonical statement, or with `threshold` > 0 the most similar
+        // candidate whose statement is at least `threshold` similar
+        let candidates = self.dedup_candidates(nmemory);
+        let key = canonical_text(&nmemory.content);
+        if let Some(id) = candidates.iter().find(|id| {
+            self.get(**id)
+                .is_some_and(|m| canonical_text(&m.content) == key)
+        }) {
+            return Some(*id);
+        }
+        if threshold <= 0.0 {
+            return None;
+        }
+        candidates
+            .into_iter()
+            .filter_map(|id| {
+                let m = self.get(id)?;
+                let score = text_similarity(&m.content, &nmemory.content);
+                (score >= threshold).then_some((id, score))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id)
+    }
+
+    pub fn digest(&self) -> Option<&MemoryBlock> {
+        self.memory.iter().find(|m| m.memtype == MemType::Digest)
+    }
+
+    pub fn set_digest(&mut self, content: impl Into<String>) -> MemoryId {
+        // There is a single digest node, rewritten in place by every consolidation
+        if let Some(digest) = self
+            .memory
+            .iter_mut()
+            .find(|m| m.memtype == MemType::Digest)
+        {
+            digest.content = content.into();
+            digest.updated_at = Utc::now();
+            return digest.id;
+        }
+        self.update(
+            MemoryBlock::new()
+                .memtype(MemType::Digest)
+                .content(content)
+                .solved(true),
+        )
+    }
+
+    pub fn format_with_digest(&self) -> Option<String> {
+        // The problem context, the digest and the results found since the digest was written
+        let digest = self.digest()?;
+        Some(
+            self.memory
+                .iter()
+                .filter(|m| {
+                    Some(m.id) == self.context_id()
+                        || m.id == digest.id
+                        || (m.is_solved() && m.created_at > digest.updated_at)
+                })
+                .map(|m| {
+                    format!(
+                        "#### Memory **ID: {}**\n\n{}\n\n",
+                        m.id,
+                        m._format_with_proof_summary()
+                    )
+                })
+                .collect::<String>(),
+        )
+    }
+
+    pub fn get(&self, id: MemoryId) -> Option<&MemoryBlock> {
+        self.memory.iter().find(|m| m.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: MemoryId) -> Option<&mut MemoryBlock> {
+        self.memory.iter_mut().find(|m| m.id == id)
+    }
+
+    fn context_id(&self) -> Option<MemoryId> {
+        // The problem context is always the first memory block if it exists
+        self.memory
+            .first()
+            .filter(|m| m.memtype == MemType::Context)
+            .map(|m| m.id)
+    }
+
+    pub fn node_hash(&self, id: MemoryId) -> String {
+        // Hash of everything a review of this memory block depends on: its own statement and
+        // proof, together with the statements of the lemmas it cites
+        let Some(memblock) = self.get(id) else {
+            return String::new();
+        };
+        let mut parts: Vec<&str> = vec![memblock.memtype.env(), &memblock.content, &memblock.proof];
+        for dep in &memblock.deps {
+            parts.push(self.get(*dep).map_or("", |m| m.content.as_str()));
+        }
+        content_hash(&parts)
+    }
+
+    pub fn is_verified(&self, id: MemoryId) -> bool {
+        // A verification record is only valid for the exact content it was issued for
+        self.get(id)
+            .is_some_and(|m| !m.verified_hash.is_empty() && m.verified_hash == self.node_hash(id))
     }
-    pub fn get_proof_path_ids(&self, id: usize, include_end_node: bool) -> Vec<usize> {
+
+    pub fn mark_verified(&mut self, id: MemoryId) {
+        let hash = self.node_hash(id);
+        if let Some(memblock) = self.get_mut(id) {
+            memblock.set_verified_hash(hash);
+        }
+    }
+
+    pub fn get_proof_path_ids(&self, id: MemoryId, include_end_node: bool) -> Vec<MemoryId> {
         // Format the given memory ID and all dependencies of it
         // ids are sorted in the increasing order
-        let mut dep_ids: Vec<usize> = vec![id];
+        let mut dep_ids: Vec<MemoryId> = vec![id];
         let mut retrieve_id: usize = 0;
 
-        while let Some(memblock) = &self.memory.get(dep_ids[retrieve_id]) {
-            for id in &memblock.deps {
-                if !dep_ids.contains(id) {
-                    dep_ids.push(*id);
+        while retrieve_id < dep_ids.len() {
+            if let Some(memblock) = self.get(dep_ids[retrieve_id]) {
+                for id in &memblock.deps {
+                    if !dep_ids.contains(id) {
+                        dep_ids.push(*id);
+                    }
                 }
             }
-
             retrieve_id += 1;
-            if retrieve_id >= dep_ids.len() {
-                break;
-            }
         }
         if !include_end_node {
             dep_ids.remove(0);
         }
+        dep_ids.retain(|i| {
+            let exists = self.get(*i).is_some();
+            if !exists {
+                warn!("Ignoring nonexistent memory ID {} in the proof path", i);
+            }
+            exists
+        });
 
-        // add the context information in memory id: 0 if exists
-        if let Some(memblock) = &self.memory.get(0) {
-            if !dep_ids.contains(&0) && memblock.memtype == "context" {
-                dep_ids.push(0);
+        // add the context information if exists
+        if let Some(context_id) = self.context_id() {
+            if !dep_ids.contains(&context_id) {
+                dep_ids.push(context_id);
             }
         }
 
@@ -199,24 +658,139 @@ impl Memory {
         return dep_ids;
     }
 
-    //    pub fn get_subgraph_ids(&self, id: usize) -> Vec<usize> {
-    //        // Get all the memory ids derived from the given id
-    //        let mut dev_ids: Vec<usize> = vec![id];
-    //        for i in id+1 .. self.memory.len() {
-    //            let memblock = &self.memory[i];
-    //            for dep_id in &memblock.deps {
-    //                if dev_ids.contains(dep_id) && !dev_ids.contains(&i) {
-    //                    dev_ids.push(i);
-    //                    break;
-    //                }
-    //            }
-    //        }
-    //        return dev_ids;
-    //    }
+    /// The id the next inserted memory block will receive
+    pub fn next_id(&self) -> MemoryId {
+        self.next_id
+    }
+
+    pub fn validate_deps(
+        &self,
+        deps: &[MemoryId],
+        reserved: usize,
+        require_verified: bool,
+    ) -> Result<Vec<MemoryId>, Vec<String>> {
+        // Check the dependencies of a new memory block that is not inserted yet. The ids from
+        // `next_id` on are reserved for the results of the same response in order: the first
+        // `reserved` of them may be cited, the next one is the new block itself. With
+        // `require_verified`, reviewed kinds can only be cited after passing their review.
+        // Returns the normalized dependency list, or a list of human readable diagnostics.
+        let own_id = self.next_id + reserved;
+        let mut valid: Vec<MemoryId> = Vec::new();
+        let mut diagnostics: Vec<String> = Vec::new();
+        for dep in deps {
+            if valid.contains(dep) {
+                continue;
+            }
+            if (self.next_id..own_id).contains(dep) {
+                valid.push(*dep);
+                continue;
+            }
+            let Some(memblock) = self.get(*dep) else {
+                diagnostics.push(if *dep == own_id {
+                    format!("Memory ID {} refers to the new result itself.", dep)
+                } else {
+                    format!("Memory ID {} does not exist.", dep)
+                });
+                continue;
+            };
+            // The context is always available to every result, no need to cite it
+            if memblock.memtype == MemType::Context {
+                continue;
+            }
+            if !memblock.memtype.is_citable() {
+                diagnostics.push(format!(
+                    "Memory ID {} is a {} and cannot be used as a dependency.",
+                    dep, memblock.memtype
+                ));
+            } else if !memblock.is_solved() {
+                diagnostics.push(format!(
+                    "Memory ID {} was refuted or is unproven and cannot be used as a dependency.",
+                    dep
+                ));
+            } else if require_verified && memblock.memtype.needs_review() && !self.is_verified(*dep)
+            {
+                diagnostics.push(format!(
+                    "Memory ID {} has not been verified and cannot be used as a dependency.",
+                    dep
+                ));
+            } else {
+                valid.push(*dep);
+            }
+        }
+        if diagnostics.is_empty() {
+            Ok(valid)
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    pub fn check_new_deps(
+        &self,
+        raw_deps: &str,
+        reserved: usize,
+        require_verified: bool,
+    ) -> Result<Vec<MemoryId>, Vec<String>> {
+        // Parse and validate the dependency list of a new memory block proposed by the explorer
+        let deps = serde_json::from_str::<Vec<MemoryId>>(raw_deps.trim()).map_err(|e| {
+            vec![format!(
+                "The dependency `{}` is not a JSON array of memory IDs: {}.",
+                raw_deps.trim(),
+                e
+            )]
+        })?;
+        self.validate_deps(&deps, reserved, require_verified)
+    }
+
+    pub fn get_dependent_ids(&self, id: MemoryId) -> Vec<MemoryId> {
+        // Get all the memory ids that depend on the given id directly or transitively
+        // ids are sorted in the increasing order, the given id itself is excluded
+        let mut dev_ids: Vec<MemoryId> = Vec::new();
+        let mut frontier: Vec<MemoryId> = vec![id];
+        while let Some(cur) = frontier.pop() {
+            for memblock in &self.memory {
+                if memblock.id != id
+                    && memblock.deps.contains(&cur)
+                    && !dev_ids.contains(&memblock.id)
+                {
+                    dev_ids.push(memblock.id);
+                    frontier.push(memblock.id);
+                }
+            }
+        }
+        dev_ids.sort_unstable();
+        dev_ids
+    }
+
+    pub fn invalidate_dependents(&mut self, id: MemoryId) -> Vec<MemoryId> {
+        // Mark every memory block built upon the given id as unsolved, so that no result is
+        // claimed on top of a lemma that has been refuted or whose statement has changed
+        let dev_ids = self.get_dependent_ids(id);
+        for i in &dev_ids {
+            if let Some(memblock) = self.get_mut(*i) {
+                memblock.invalidate();
+            }
+        }
+        if !dev_ids.is_empty() {
+            info!(
+                "Invalidated {} memory blocks depending on memory ID {}: {:?}",
+                dev_ids.len(),
+                id,
+                &dev_ids
+            );
+        }
+        dev_ids
+    }
+
+    pub fn is_grounded(&self, id: MemoryId) -> bool {
+        // Whether the given memory block and everything in its proof path are solved
+        self.get_proof_path_ids(id, true)
+            .iter()
+            .all(|i| self.get(*i).is_some_and(|m| m.is_solved()))
+    }
 
     pub fn format_deps(
         &self,
-        id: usize,
+        id: MemoryId,
         with_proof: bool,
         include_end_node: bool,
     ) -> Option<String> {
@@ -225,67 +799,34 @@ impl Memory {
 
     pub fn format_deps_with_order(
         &self,
-        id: usize,
+        id: MemoryId,
         with_proof: bool,
         include_end_node: bool,
         reverse_order: bool,
     ) -> Option<String> {
         let mut dep_ids = self.get_proof_path_ids(id, include_end_node);
-        let mut res = String::new();
-        // add the context information in memory id: 0 if exists
-        if let Some(memblock) = &self.memory.get(0) {
-            if !dep_ids.contains(&0) && memblock.memtype == "context" {
-                dep_ids.push(0);
-            }
+        if reverse_order {
+            dep_ids.reverse();
         }
 
-        if reverse_order {
-            for id in dep_ids.iter().rev() {
-                if let Some(memblock) = &self.memory.get(*id) {
-                    res.push_str(&format!(
-                        "#### Memory **ID: {}**\n\n{}\n\n",
-                        id,
-                        if with_proof {
-                            memblock._format_with_proof()
-                        } else {
-                            memblock._format()
-                        }
-                    ));
-                }
-            }
-        } else {
-            for id in &dep_ids {
-                if let Some(memblock) = &self.memory.get(*id) {
-                    res.push_str(&format!(
-                        "#### Memory **ID: {}**\n\n{}\n\n",
-                        id,
-                        if with_proof {
-                            memblock._format_with_proof()
-                        } else {
-                            memblock._format()
-                        }
-                    ));
-                }
+        let mut res = String::new();
+        for id in &dep_ids {
+            if let Some(memblock) = self.get(*id) {
+                res.push_str(&format!(
+                    "#### Memory **ID: {}**\n\n{}\n\n",
+                    id,
+                    if with_proof {
+                        memblock._format_with_proof()
+                    } else {
+                        memblock._format()
+                    }
+                ));
             }
         }
 
         if res.is_empty() { None } else { Some(res) }
     }
-    // pub fn format_all(&self, solved_only: bool) -> Option<String> {
-    //     // Format all memory blocks as the input of other agents
-    //     if self.memory.is_empty() {
-    //         None
-    //     } else {
-    //         Some(
-    //             self.memory
-    //                 .iter()
-    //                 .enumerate()
-    //                 .filter(|(_, mem)| if solved_only {mem.is_solved()} else {true})
-    //                 .map(|(i, mem)| format!("#### Memory **ID: {i}**\n\n{}\n\n", mem._format()))
-    //                 .collect::<String>(),
-    //         )
-    //     }
-    // }
+
     pub fn format_all_with_proof(&self, solved_only: bool) -> Option<String> {
         // Format all memory blocks with proofs as the final output
         if self.memory.is_empty() {
@@ -294,11 +835,11 @@ impl Memory {
             Some(
                 self.memory
                     .iter()
-                    .enumerate()
-                    .filter(|(_, mem)| if solved_only { mem.is_solved() } else { true })
-                    .map(|(i, mem)| {
+                    .filter(|mem| if solved_only { mem.is_solved() } else { true })
+                    .map(|mem| {
                         format!(
-                            "#### Memory **ID: {i}**\n\n{}\n\n",
+                            "#### Memory **ID: {}**\n\n{}\n\n",
+                            mem.id,
                             mem._format_with_proof()
                         )
                     })
@@ -307,25 +848,340 @@ impl Memory {
         }
     }
 
-    pub fn format_all_with_proof_summary(&self, solved_only: bool) -> Option<String> {
-        // Format all memory blocks with proofs and proof summaries as the final output
-        if self.memory.is_empty() {
-            None
-        } else {
-            Some(
-                self.memory
-                    .iter()
-                    .enumerate()
-                    .filter(|(_, mem)| if solved_only { mem.is_solved() } else { true })
-                    .map(|(i, mem)| {
-                        format!(
-                            "#### Memory **ID: {i}**\n\n{}\n\n",
-                            mem._format_with_proof_summary()
-                        )
-                    })
-                    .collect::<String>(),
-            )
+    fn dependency_distances(&self, focus: &[MemoryId]) -> HashMap<MemoryId, usize> {
+        // Breadth first distances from the focus nodes along their dependencies
+        let mut distances: HashMap<MemoryId, usize> = focus.iter().map(|id| (*id, 0)).collect();
+        let mut frontier: VecDeque<MemoryId> = focus.iter().copied().collect();
+        while let Some(cur) = frontier.pop_front() {
+            let Some(memblock) = self.get(cur) else {
+                continue;
+            };
+            let dist = distances[&cur] + 1;
+            for dep in &memblock.deps {
+                if !distances.contains_key(dep) {
+                    distances.insert(*dep, dist);
+                    frontier.push_back(*dep);
+                }
+            }
+        }
+        distances
+    }
+
+    pub fn select_context(
+        &self,
+        goal: &str,
+        focus: &[MemoryId],
+        token_budget: usize,
+    ) -> ContextSelection {
+        // Solved memory blocks with their proof summaries
+        self.select_blocks(goal, focus, token_budget, false)
+    }
+
+    pub fn select_statements(&self, goal: &str, token_budget: usize) -> ContextSelection {
+        // Statements of every memory block except the digest, including unsolved ones
+        self.select_blocks(goal, &[], token_budget, true)
+    }
+
+    fn select_blocks(
+        &self,
+        goal: &str,
+        focus: &[MemoryId],
+        token_budget: usize,
+        statements: bool,
+    ) -> ContextSelection {
+        // Rank memory blocks by their relevance to the goal and fill the token budget,
+        // a budget of 0 includes everything. The problem context is always kept.
+        let format_block = |m: &MemoryBlock| {
+            if statements {
+                let status = if m.is_solved() { "" } else { " (unsolved)" };
+                format!(
+                    "#### Memory **ID: {}**{}\n\n{}\n\n",
+                    m.id,
+                    status,
+                    m._format_statement()
+                )
+            } else {
+                format!(
+                    "#### Memory **ID: {}**\n\n{}\n\n",
+                    m.id,
+                    m._format_with_proof_summary()
+                )
+            }
+        };
+        let candidates: Vec<&MemoryBlock> = self
+            .memory
+            .iter()
+            .filter(|m| (statements || m.is_solved()) && m.memtype != MemType::Digest)
+            .collect();
+        if candidates.is_empty() {
+            return ContextSelection::default();
+        }
+        let distances = self.dependency_distances(focus);
+        let usage = |m: &MemoryBlock| {
+            self.memory
+                .iter()
+                .filter(|d| d.deps.contains(&m.id))
+                .count() as f64
+                + m.citations as f64
+        };
+        let max_usage = candidates.iter().map(|m| usage(m)).fold(1.0, f64::max);
+        let last = (candidates.len() - 1).max(1) as f64;
+        let context_id = self.context_id();
+
+        let mut ranked: Vec<(f64, usize, MemoryId, String, usize)> = candidates
+            .iter()
+            .enumerate()
+            .map(|(pos, m)| {
+                let text = format_block(m);
+                let tokens = estimate_tokens(&text);
+                let score = if Some(m.id) == context_id {
+                    f64::INFINITY
+                } else {
+                    let dependency = distances.get(&m.id).map_or(0.0, |d| 1.0 / (1 + d) as f64);
+                    DEPENDENCY_WEIGHT * dependency
+                        + OVERLAP_WEIGHT * text_similarity(goal, &m.content)
+                        + RECENCY_WEIGHT * pos as f64 / last
+                        + USAGE_WEIGHT * usage(m) / max_usage
+                };
+                (score, pos, m.id, text, tokens)
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut selection = ContextSelection::default();
+        let mut chosen: Vec<(usize, MemoryId, String, usize)> = Vec::new();
+        let mut used = 0;
+        for (score, pos, id, text, tokens) in ranked {
+            if token_budget == 0 || score.is_infinite() || used + tokens <= token_budget {
+                used += tokens;
+                chosen.push((pos, id, text, tokens));
+            } else {
+                selection.omitted.push((id, tokens));
+            }
+        }
+        // Keep the included blocks in memory order so that dependencies come first
+        chosen.sort_by_key(|(pos, ..)| *pos);
+        let mut text = String::new();
+        for (_, id, block_text, tokens) in chosen {
+            selection.included.push((id, tokens));
+            text.push_str(&block_text);
+        }
+        selection.text = Some(text);
+        selection
+    }
+}
+
+/// API usage of a session, shared by the clients of all its agents
+pub struct Telemetry {
+    started: Instant,
+    previous_millis: AtomicU64,
+    calls: AtomicU64,
+    prompt_tokens: AtomicU64,
+    completion_tokens: AtomicU64,
+    roles: Mutex<BTreeMap<&'static str, RoleSettings>>,
+    budget: Budget,
+    log: OnceLock<Arc<JsonlLog>>,
+}
+
+/// Limits on the API usage of a session, 0 for unlimited, and the prices its cost is estimated
+/// with in USD per million tokens
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    pub tokens: u64,
+    pub cost: f64,
+    pub minutes: u64,
+    pub calls: u64,
+    pub input_price: f64,
+    pub output_price: f64,
+}
+
+/// The settings an agent role was called with, and the models that actually answered
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RoleSettings {
+    pub calls: u64,
+    pub models: BTreeSet<String>,
+    pub response_models: BTreeSet<String>,
+    pub reasoning_efforts: BTreeSet<String>,
+    pub temperature: Option<f64>,
+}
+
+/// An agent call as written to the session log
+#[derive(Serialize)]
+struct CallRecord<'a> {
+    role: &'a str,
+    model: &'a str,
+    response_model: Option<&'a str>,
+    reasoning_effort: &'a str,
+    prompt_hash: String,
+    prompt: &'a str,
+    response: &'a str,
+    reasoning_chars: usize,
+    latency_ms: u128,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    outcome: String,
+}
+
+/// A completion streamed from the API
+struct Completion {
+    content: String,
+    model: Option<String>,
+    reasoning_chars: usize,
+    usage: Option<(u64, u64)>,
+}
+
+/// Embeddings returned by the API
+struct Embeddings {
+    embeddings: Vec<Vec<f32>>,
+    model: Option<String>,
+    prompt_tokens: Option<u64>,
+}
+
+/// A snapshot of the API usage recorded by a `Telemetry`
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub elapsed: Duration,
+}
+
+impl Telemetry {
+    pub fn new() -> Self {
+        Telemetry {
+            started: Instant::now(),
+            previous_millis: AtomicU64::new(0),
+            calls: AtomicU64::new(0),
+            prompt_tokens: AtomicU64::new(0),
+            completion_tokens: AtomicU64::new(0),
+            roles: Mutex::new(BTreeMap::new()),
+            budget: Budget::default(),
+            log: OnceLock::new(),
+        }
+    }
+    /// Refuse agent calls once the given budget is used up
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+    /// Also write every agent call to the given session log
+    pub fn log(self, log: Arc<JsonlLog>) -> Self {
+        self.attach_log(log);
+        self
+    }
+    /// Write the agent calls from now on to the given session log, once no log is set
+    pub fn attach_log(&self, log: Arc<JsonlLog>) {
+        if self.log.set(log).is_err() {
+            warn!("The telemetry already writes to a session log");
+        }
+    }
+    fn log_call(&self, record: &CallRecord) {
+        self.write_log("agent_call", record);
+    }
+    fn record_role(
+        &self,
+        role: &'static str,
+        model: &str,
+        response_model: Option<&str>,
+        reasoning_effort: Option<&str>,
+        temperature: Option<f64>,
+    ) {
+        let Ok(mut roles) = self.roles.lock() else {
+            return;
+        };
+        let settings = roles.entry(role).or_default();
+        settings.calls += 1;
+        settings.models.insert(model.to_string());
+        if let Some(response_model) = response_model {
+            settings.response_models.insert(response_model.to_string());
+        }
+        if let Some(reasoning_effort) = reasoning_effort {
+            settings
+                .reasoning_efforts
+                .insert(reasoning_effort.to_string());
+        }
+        settings.temperature = temperature;
+    }
+    /// Settings of every agent role called so far
+    pub fn roles(&self) -> BTreeMap<&'static str, RoleSettings> {
+        self.roles.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+    /// Append a record of the given kind to the session log
+    pub fn write_log(&self, kind: &str, record: &impl Serialize) {
+        if let Some(log) = self.log.get() {
+            log.write(kind, record);
+        }
+    }
+    /// Count a logical agent call; its retries are not counted against the call budget
+    fn record_call(&self) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+    }
+    fn record_tokens(&self, prompt_tokens: u64, completion_tokens: u64) {
+        self.prompt_tokens
+            .fetch_add(prompt_tokens, Ordering::Relaxed);
+        self.completion_tokens
+            .fetch_add(completion_tokens, Ordering::Relaxed);
+    }
+    /// Add the usage of an earlier run of the same session
+    pub fn restore(&self, usage: Usage) {
+        self.calls.fetch_add(usage.calls, Ordering::Relaxed);
+        self.record_tokens(usage.prompt_tokens, usage.completion_tokens);
+        self.previous_millis
+            .fetch_add(usage.elapsed.as_millis() as u64, Ordering::Relaxed);
+    }
+    pub fn usage(&self) -> Usage {
+        Usage {
+            calls: self.calls.load(Ordering::Relaxed),
+            prompt_tokens: self.prompt_tokens.load(Ordering::Relaxed),
+            completion_tokens: self.completion_tokens.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed()
+                + Duration::from_millis(self.previous_millis.load(Ordering::Relaxed)),
+        }
+    }
+    /// Estimated cost of the given usage in USD
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.prompt_tokens as f64 * self.budget.input_price
+            + usage.completion_tokens as f64 * self.budget.output_price)
+            / 1_000_000.0
+    }
+    /// Describe the first budget that is used up, if any
+    pub fn exhausted(&self) -> Option<String> {
+        let usage = self.usage();
+        let budget = &self.budget;
+        if budget.tokens > 0 && usage.tokens() >= budget.tokens {
+            return Some(format!(
+                "{} of {} tokens used",
+                usage.tokens(),
+                budget.tokens
+            ));
+        }
+        if budget.cost > 0.0 && self.cost(&usage) >= budget.cost {
+            return Some(format!(
+                "${:.2} of ${:.2} spent",
+                self.cost(&usage),
+                budget.cost
+            ));
         }
+        if budget.minutes > 0 && usage.elapsed.as_secs() >= budget.minutes * 60 {
+            return Some(format!(
+                "{} of {} minutes elapsed",
+                usage.elapsed.as_secs() / 60,
+                budget.minutes
+            ));
+        }
+        if budget.calls > 0 && usage.calls >= budget.calls {
+            return Some(format!(
+                "{} of {} API calls made",
+                usage.calls, budget.calls
+            ));
+        }
+        None
+    }
+}
+
+impl Usage {
+    pub fn tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
     }
 }
 
@@ -334,13 +1190,15 @@ pub struct LMClient {
     client: reqwest::Client,
     api_key: String,
     base_url: String,
+    role: &'static str,
+    telemetry: Option<Arc<Telemetry>>,
 }
 
 impl LMClient {
     pub fn new() -> Self {
+        // The variables can also be set in the environment, without a .env file
         if let Err(e) = dotenv() {
-            error!("Error occured when loading .env file: {}", e);
-            panic!();
+            debug!("No .env file loaded: {}", e);
         }
         let api_key = match env::var("OPENAI_API_KEY") {
             Ok(key) => key,
@@ -361,9 +1219,37 @@ impl LMClient {
             client: client,
             api_key: api_key,
             base_url: base_url.into(),
+            role: "",
+            telemetry: None,
+        }
+    }
+
+    /// Name of the agent using this client, recorded in the session log
+    fn role(mut self, role: &'static str) -> Self {
+        self.role = role;
+        self
+    }
+
+    fn check_budget(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Concurrent calls of a step stop here once the session has used up its budget
+        match self.telemetry.as_ref().and_then(|t| t.exhausted()) {
+            Some(reason) => Err(format!("Budget exhausted: {}", reason).into()),
+            None => Ok(()),
         }
     }
 
+    #[tracing::instrument(
+        name = "agent_call",
+        skip_all,
+        fields(
+            role = self.role,
+            model,
+            reasoning_effort,
+            attempt = tracing::field::Empty,
+            prompt_tokens = tracing::field::Empty,
+            completion_tokens = tracing::field::Empty,
+        )
+    )]
     async fn comp(
         &self,
         prompt: &str,
@@ -371,15 +1257,81 @@ impl LMClient {
         stream_output: bool,
         reasoning_effort: &str,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        // Always send stream=true since SSE client expects event stream
-        // Always include reasoning_effort; non-supporting models ignore it.
-        let request_body = json!({
-            "model": model,
-            "messages": [
-                {"role": "user", "content": prompt}
-            ],
-            "temperature": 1.0,
-            "stream": true,
+        self.check_budget()?;
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record_call();
+        }
+        let started = Instant::now();
+        let result = self
+            .request_completion(prompt, model, stream_output, reasoning_effort)
+            .await;
+        if let Ok(Completion {
+            usage: Some((prompt_tokens, completion_tokens)),
+            ..
+        }) = &result
+        {
+            let span = tracing::Span::current();
+            span.record("prompt_tokens", prompt_tokens);
+            span.record("completion_tokens", completion_tokens);
+        }
+        let Some(telemetry) = &self.telemetry else {
+            return result.map(|c| c.content);
+        };
+        // Estimate the usage when the API does not report it
+        let (prompt_tokens, completion_tokens) = match &result {
+            Ok(c) => c.usage.unwrap_or((
+                estimate_tokens(prompt) as u64,
+                estimate_tokens(&c.content) as u64,
+            )),
+            Err(_) => (0, 0),
+        };
+        let response_model = result.as_ref().ok().and_then(|c| c.model.as_deref());
+        telemetry.record_tokens(prompt_tokens, completion_tokens);
+        telemetry.record_role(
+            self.role,
+            model,
+            response_model,
+            Some(reasoning_effort),
+            Some(TEMPERATURE),
+        );
+        telemetry.log_call(&CallRecord {
+            role: self.role,
+            model,
+            response_model,
+            reasoning_effort,
+            prompt_hash: content_hash(&[prompt]),
+            prompt,
+            response: result.as_ref().map_or("", |c| c.content.as_str()),
+            reasoning_chars: result.as_ref().map_or(0, |c| c.reasoning_chars),
+            latency_ms: started.elapsed().as_millis(),
+            prompt_tokens,
+            completion_tokens,
+            outcome: match &result {
+                Ok(_) => "ok".into(),
+                Err(e) => format!("error: {}", e),
+            },
+        });
+        result.map(|c| c.content)
+    }
+
+    async fn request_completion(
+        &self,
+        prompt: &str,
+        model: &str,
+        stream_output: bool,
+        reasoning_effort: &str,
+    ) -> Result<Completion, Box<dyn std::error::Error + Send + Sync>> {
+        // Always send stream=true since SSE client expects event stream
+        // Always include reasoning_effort; non-supporting models ignore it.
+        // Usage is requested with stream_options, which is dropped if the API rejects it.
+        let mut request_body = json!({
+            "model": model,
+            "messages": [
+                {"role": "user", "content": prompt}
+            ],
+            "temperature": TEMPERATURE,
+            "stream": true,
+            "stream_options": {"include_usage": true},
             "reasoning_effort": reasoning_effort
         });
         let url = format!(
@@ -395,6 +1347,7 @@ impl LMClient {
                 &url, attempt, &request_body
             );
             attempt += 1;
+            tracing::Span::current().record("attempt", attempt);
 
             let response = self
                 .client
@@ -411,6 +1364,9 @@ impl LMClient {
                     let mut content_buffer = String::new();
                     let mut sse_buffer = String::new();
                     let mut chunk_decode_retries: u8 = 0;
+                    let mut usage: Option<(u64, u64)> = None;
+                    let mut response_model: Option<String> = None;
+                    let mut reasoning_chars: usize = 0;
 
                     while let Some(chunk) = stream.next().await {
                         let chunk = match chunk {
@@ -440,10 +1396,26 @@ impl LMClient {
 
                                 match serde_json::from_str::<serde_json::Value>(data_str) {
                                     Ok(data) => {
+                                        if response_model.is_none()
+                                            && let Some(m) = data["model"].as_str()
+                                            && !m.is_empty()
+                                        {
+                                            response_model = Some(m.to_string());
+                                        }
+                                        if let Some(prompt_tokens) =
+                                            data["usage"]["prompt_tokens"].as_u64()
+                                        {
+                                            let completion_tokens =
+                                                data["usage"]["completion_tokens"]
+                                                    .as_u64()
+                                                    .unwrap_or_default();
+                                            usage = Some((prompt_tokens, completion_tokens));
+                                        }
                                         if let Some(content) =
                                             data["choices"][0]["delta"]["reasoning_content"]
                                                 .as_str()
                                         {
+                                            reasoning_chars += content.chars().count();
                                             if stream_output {
                                                 print!("{}", &content)
                                             }
@@ -469,13 +1441,25 @@ impl LMClient {
                         warn!("Remaining unprocessed data: {}", sse_buffer);
                     }
 
-                    return Ok(content_buffer);
+                    return Ok(Completion {
+                        content: content_buffer,
+                        model: response_model,
+                        reasoning_chars,
+                        usage,
+                    });
                 }
                 Ok(res) => {
                     warn!("Error occured when calling API, status: {}", res.status());
                     if attempt > MAX_REQWEST_RETRIES {
                         return Err(format!("API call failed after all {} retries", attempt).into());
                     }
+                    if res.status() == reqwest::StatusCode::BAD_REQUEST
+                        && let Some(body) = request_body.as_object_mut()
+                        && body.remove("stream_options").is_some()
+                    {
+                        warn!("Retrying without stream_options, usage will be estimated");
+                        continue;
+                    }
                     tokio::time::sleep(API_RETRY_DELAY).await;
                 }
                 Err(e) => {
@@ -488,6 +1472,230 @@ impl LMClient {
             }
         }
     }
+
+    #[tracing::instrument(
+        name = "embedding_call",
+        skip_all,
+        fields(role = self.role, model, inputs = inputs.len(), attempt = tracing::field::Empty)
+    )]
+    async fn embeddings(
+        &self,
+        inputs: &[String],
+        model: &str,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error + Send + Sync>> {
+        self.check_budget()?;
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record_call();
+        }
+        let started = Instant::now();
+        let result = self.request_embeddings(inputs, model).await;
+        let Some(telemetry) = &self.telemetry else {
+            return result.map(|e| e.embeddings);
+        };
+        // Estimate the usage when the API does not report it
+        let prompt_tokens = match &result {
+            Ok(e) => e
+                .prompt_tokens
+                .unwrap_or_else(|| inputs.iter().map(|i| estimate_tokens(i) as u64).sum()),
+            Err(_) => 0,
+        };
+        let response_model = result.as_ref().ok().and_then(|e| e.model.as_deref());
+        telemetry.record_tokens(prompt_tokens, 0);
+        telemetry.record_role(self.role, model, response_model, None, None);
+        let prompt = inputs.join("\n\n");
+        let response = match &result {
+            Ok(e) => format!(
+                "{} embeddings of dimension {}",
+                e.embeddings.len(),
+                e.embeddings.first().map_or(0, |v| v.len())
+            ),
+            Err(_) => String::new(),
+        };
+        telemetry.log_call(&CallRecord {
+            role: self.role,
+            model,
+            response_model,
+            reasoning_effort: "",
+            prompt_hash: content_hash(&[&prompt]),
+            prompt: &prompt,
+            response: &response,
+            reasoning_chars: 0,
+            latency_ms: started.elapsed().as_millis(),
+            prompt_tokens,
+            completion_tokens: 0,
+            outcome: match &result {
+                Ok(_) => format!("ok: {} inputs", inputs.len()),
+                Err(e) => format!("error: {}", e),
+            },
+        });
+        result.map(|e| e.embeddings)
+    }
+
+    async fn request_embeddings(
+        &self,
+        inputs: &[String],
+        model: &str,
+    ) -> Result<Embeddings, Box<dyn std::error::Error + Send + Sync>> {
+        let request_body = json!({
+            "model": model,
+            "input": inputs,
+        });
+        let url = format!(
+            "{}/v1/embeddings",
+            &self.base_url.trim_end_matches('/').trim_end_matches("/v1")
+        );
+
+        let mut attempt: u8 = 0;
+        loop {
+            debug!(
+                "Sending embedding request to url {}, Attempt {}",
+                &url, attempt
+            );
+            attempt += 1;
+            tracing::Span::current().record("attempt", attempt);
+            let response = self
+                .client
+                .post(&url)
+                .bearer_auth(&self.api_key)
+                .json(&request_body)
+                .send()
+                .await;
+            match response {
+                Ok(resp) if resp.status().is_success() => {
+                    let data: serde_json::Value = resp.json().await?;
+                    let mut items: Vec<(usize, Vec<f32>)> = data["data"]
+                        .as_array()
+                        .ok_or("Embedding response without data")?
+                        .iter()
+                        .enumerate()
+                        .map(|(i, item)| {
+                            let index = item["index"].as_u64().map_or(i, |v| v as usize);
+                            let embedding = item["embedding"]
+                                .as_array()
+                                .map(|v| v.iter().filter_map(|x| x.as_f64()).map(|x| x as f32))
+                                .into_iter()
+                                .flatten()
+                                .collect();
+                            (index, embedding)
+                        })
+                        .collect();
+                    if items.len() != inputs.len() {
+                        return Err(format!(
+                            "Expected {} embeddings but received {}",
+                            inputs.len(),
+                            items.len()
+                        )
+                        .into());
+                    }
+                    items.sort_by_key(|(i, _)| *i);
+                    return Ok(Embeddings {
+                        embeddings: items.into_iter().map(|(_, e)| e).collect(),
+                        model: data["model"].as_str().map(str::to_string),
+                        prompt_tokens: data["usage"]["prompt_tokens"].as_u64(),
+                    });
+                }
+                Ok(res) => {
+                    warn!(
+                        "Error occured when calling embedding API, status: {}",
+                        res.status()
+                    );
+                    if attempt > MAX_REQWEST_RETRIES {
+                        return Err(format!("API call failed after all {} retries", attempt).into());
+                    }
+                    tokio::time::sleep(API_RETRY_DELAY).await;
+                }
+                Err(e) => {
+                    warn!("Error occured when requesting embeddings with error: {}", e);
+                    if attempt > MAX_REQWEST_RETRIES {
+                        return Err(e.into());
+                    }
+                    tokio::time::sleep(API_RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+/// Prompt templates of every agent role, hashed into the run manifest
+pub const PROMPT_TEMPLATES: &[(&str, &[&str])] = &[
+    (
+        "explorer",
+        &[
+            EXPLORER_PROMPT,
+            EXPLORER_PERSONA,
+            EXPLORER_CONTEXT,
+            EXPLORER_FOCUS,
+            EXPLORER_DEAD_ENDS,
+            EXPLORER_NEXT_ID,
+            EXPLORER_FEEDBACK,
+        ],
+    ),
+    (
+        "simple_reviewer",
+        &[SIMPLE_REVIEWER_PROMPT, SIMPLE_REVIEWER_CONTEXT],
+    ),
+    (
+        "definition_reviewer",
+        &[DEFINITION_REVIEWER_PROMPT, DEFINITION_REVIEWER_CONTEXT],
+    ),
+    ("skeptic", &[SKEPTIC_PROMPT, SKEPTIC_CONTEXT]),
+    (
+        "progressive_reviewer",
+        &[
+            PROGRESSIVE_REVIEWER_PROMPT,
+            PROGRESSIVE_REVIEWER_CHUNK_PROMPT,
+            PROGRESSIVE_REVIEWER_CONTEXT,
+        ],
+    ),
+    ("refiner", &[REFINER_PROMPT, REFINER_CONTEXT]),
+    ("formatter", &[FORMATTER_PROMPT, FORMATTER_KIND]),
+    ("context_generator", &[CONTEXT_GENERATOR_PROMPT]),
+    ("proof_summarizer", &[PROOF_SUMMARIZER_PROMPT]),
+    (
+        "consolidator",
+        &[CONSOLIDATOR_PROMPT, CONSOLIDATOR_PREVIOUS_DIGEST],
+    ),
+    ("screener", &[SCREENER_PROMPT, SCREENER_CONTEXT]),
+    (
+        "planner",
+        &[PLANNER_PROMPT, PLANNER_CONTEXT, PLANNER_REVISION],
+    ),
+    ("variant_generator", &[VARIANT_GENERATOR_PROMPT]),
+];
+
+/// Fills the `{{name}}` placeholders of a template without rescanning the filled values
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut prompt = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        prompt.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            values
+                .iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(_, value)| (end, *value))
+        });
+        match value {
+            Some((end, value)) => {
+                prompt.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                prompt.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    prompt.push_str(rest);
+    prompt
+}
+
+/// Fills an optional prompt section, which is left out without a value
+fn section(template: &str, name: &str, value: Option<&str>) -> String {
+    value
+        .map(|value| fill(template, &[(name, value)]))
+        .unwrap_or_default()
 }
 
 #[async_trait::async_trait]
@@ -495,23 +1703,34 @@ pub trait Agent: Send {
     async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>>;
 }
 
+#[derive(Clone)]
 pub struct Explorer {
     client: LMClient,
     model: String,
     problem: String,
     streaming: bool,
+    persona: Option<String>,
+    focus: Option<String>,
     context: Option<String>,
+    dead_ends: Option<String>,
+    feedback: Option<String>,
+    next_id: Option<MemoryId>,
     reasoning_effort: String,
 }
 
 impl Explorer {
     pub fn new() -> Self {
         Explorer {
-            client: LMClient::new(),
+            client: LMClient::new().role("explorer"),
             model: String::new(),
             problem: String::new(),
             streaming: false,
+            persona: None,
+            focus: None,
             context: None,
+            dead_ends: None,
+            feedback: None,
+            next_id: None,
             reasoning_effort: "medium".into(),
         }
     }
@@ -519,10 +1738,40 @@ impl Explorer {
         self.model = model.into();
         self
     }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
     pub fn streaming(mut self, streaming: bool) -> Self {
         self.streaming = streaming;
         self
     }
+    /// First memory ID the results of the next response receive, announced so that they can
+    /// cite each other. Cleared with `None`.
+    pub fn set_next_id(&mut self, next_id: Option<MemoryId>) -> &Self {
+        self.next_id = next_id;
+        self
+    }
+    /// Research style that varies explorations sampled in parallel
+    pub fn persona(mut self, persona: Option<String>) -> Self {
+        self.persona = persona;
+        self
+    }
+    /// A memory node the explorer should build on in its next exploration, cleared with `None`
+    pub fn set_focus(&mut self, focus: Option<String>) -> &Self {
+        self.focus = focus;
+        self
+    }
+    /// Refuted conjectures and failed attempts the explorer should not repeat
+    pub fn set_dead_ends(&mut self, dead_ends: Option<String>) -> &Self {
+        self.dead_ends = dead_ends;
+        self
+    }
+    /// Feedback on the previous response of the explorer, cleared with `None`
+    pub fn set_feedback(&mut self, feedback: Option<String>) -> &Self {
+        self.feedback = feedback;
+        self
+    }
     pub fn set_problem(&mut self, problem: impl Into<String>) -> &Self {
         self.problem = problem.into();
         self
@@ -535,49 +1784,82 @@ impl Explorer {
         self.reasoning_effort = effort.into();
         self
     }
+    fn prompt(&self) -> String {
+        let next_id = self.next_id.map(|id| id.to_string());
+        fill(
+            EXPLORER_PROMPT,
+            &[
+                ("problem", &self.problem),
+                (
+                    "persona",
+                    &section(EXPLORER_PERSONA, "persona", self.persona.as_deref()),
+                ),
+                (
+                    "context",
+                    &section(EXPLORER_CONTEXT, "context", self.context.as_deref()),
+                ),
+                (
+                    "focus",
+                    &section(EXPLORER_FOCUS, "focus", self.focus.as_deref()),
+                ),
+                (
+                    "dead_ends",
+                    &section(EXPLORER_DEAD_ENDS, "dead_ends", self.dead_ends.as_deref()),
+                ),
+                (
+                    "next_id",
+                    &section(EXPLORER_NEXT_ID, "next_id", next_id.as_deref()),
+                ),
+                (
+                    "feedback",
+                    &section(EXPLORER_FEEDBACK, "feedback", self.feedback.as_deref()),
+                ),
+            ],
+        )
+    }
 }
 
+const EXPLORER_PROMPT: &str = concat!(
+    "### Instruction\n",
+    "\n",
+    "You are an expert that is knowledgeable across all domains in math. This time you are asked to help with our frontier math research. Its statement is as follows:\n",
+    "\n",
+    "\\begin{problem}{{problem}}\\end{problem}",
+    "\n",
+    "This problem could be difficult and not able to be directly solved, but you can make your contribution with the following instructions:\n",
+    "\n",
+    "1. You are required to explore different approaches or directions that might help with our final goal, and write down one interesting finding in your explorations as a new conjecture in your response. DO NOT claim that you can not do this job.\n",
+    "2. Your conjecture must contain the complete definitions required within it, such that it is able to stand alone as an independent lemma, unless it is declared in memory. It should be a novel conjecture that marks concrete achievements and is not similar to any existing lemmas.\n",
+    "3. You should wrap your finding inside a latex environment: \\begin{conjecture}\\end{conjecture}. This conjecture should be equipped with a detailed, complete and rigorous proof. You should explicitly write down every intermediate derivation step in the proof. The corresponding proof should be wrapped in \\begin{proof}\\end{proof} directly followed by the conjecture.\n",
+    "4. After these components you should also provide the dependency of this conjecture. You need to write down the memory IDs of lemmas used in this conjecture in a JSON array format, and warp them inside \\begin{dependency}\\end{dependency}. Only memory IDs of lemmas listed in the context below can be used. For example, a dependency of a new conjecture could be \\begin{dependency}[0, 3, 4]\\end{dependency}. You can use an empty array \"[]\" when this conjecture does not depend on other lemmas.\n",
+    "\n",
+    "More accurately, your response should obey the following format:\n",
+    "\n",
+    "\\begin{conjecture}Your new findings here\\end{conjecture}\n",
+    "\\begin{proof}Your proof of the conjecture above\\end{proof}\n",
+    "\\begin{dependency}An json array of related memory IDs of this conjecture\\end{dependency}",
+    "\n",
+    "Moreover, when you think the time is right that you are able to prove the original problem, you can simply state your proof inside \\begin{final_proof}\\end{final_proof}, and explicitly write down its dependency in \\begin{dependency}\\end{dependency}. In this case, you do not need to propose any new conjectures for this problem.",
+    "{{persona}}{{context}}{{focus}}{{dead_ends}}{{next_id}}{{feedback}}",
+);
+const EXPLORER_PERSONA: &str = "\n\n### Research Style\n\n{{persona}}";
+const EXPLORER_CONTEXT: &str = "\n\nHere is a list of context that we have collected for this problem or our history findings during exploration. They can be accepted without controversy as correct, and you can begin your exploration based on them.\n\n### Context and History Explorations\n\n{{context}}";
+const EXPLORER_FOCUS: &str = "\n\n### Focus of This Exploration\n\nPlease build your new conjecture on the following result and cite it in the dependency. It was chosen as the most promising direction of our research.\n\n{{focus}}";
+const EXPLORER_DEAD_ENDS: &str = "\n\n### Known Dead Ends\n\nThe following conjectures were refuted or the following attempts failed to pass our review in previous explorations. Do not propose them again, and avoid approaches that rely on them.\n\n{{dead_ends}}";
+const EXPLORER_NEXT_ID: &str = "\n\n### Memory IDs of New Results\n\nThe conjectures in your response receive memory IDs from {{next_id}} on, in the order they appear. A later conjecture or the final proof in the same response may cite an earlier conjecture by its ID.";
+const EXPLORER_FEEDBACK: &str = "\n\n### Feedback on Your Previous Response\n\nYour previous response to this task was rejected for the following reasons. Please take them into account and avoid repeating the same mistakes.\n\n{{feedback}}";
+
 #[async_trait::async_trait]
 impl Agent for Explorer {
     async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        let problem_stat = format!("\\begin{{problem}}{}\\end{{problem}}", &self.problem);
-        let mut context_prefix = String::new();
-        if let Some(context) = &self.context {
-            context_prefix = format!(
-                "\n\nHere is a list of context that we have collected for this problem or our history findings during exploration. They can be accepted without controversy as correct, and you can begin your exploration based on them.\n\n### Context and History Explorations\n\n{}",
-                context
-            );
-        }
-        let prompt = (concat!(
-            "### Instruction\n",
-            "\n",
-            "You are an expert that is knowledgeable across all domains in math. This time you are asked to help with our frontier math research. Its statement is as follows:\n",
-            "\n"
-        )
-        .to_string()
-            + &problem_stat
-            + concat!(
-                "\n",
-                "This problem could be difficult and not able to be directly solved, but you can make your contribution with the following instructions:\n",
-                "\n",
-                "1. You are required to explore different approaches or directions that might help with our final goal, and write down one interesting finding in your explorations as a new conjecture in your response. DO NOT claim that you can not do this job.\n",
-                "2. Your conjecture must contain the complete definitions required within it, such that it is able to stand alone as an independent lemma, unless it is declared in memory. It should be a novel conjecture that marks concrete achievements and is not similar to any existing lemmas.\n",
-                "3. You should wrap your finding inside a latex environment: \\begin{conjecture}\\end{conjecture}. This conjecture should be equipped with a detailed, complete and rigorous proof. You should explicitly write down every intermediate derivation step in the proof. The corresponding proof should be wrapped in \\begin{proof}\\end{proof} directly followed by the conjecture.\n",
-                "4. After these components you should also provide the dependency of this conjecture. You need to write down the memory IDs of lemmas used in this conjecture in a JSON array format, and warp them inside \\begin{dependency}\\end{dependency}. For example, a dependency of a new conjecture could be \\begin{dependency}[0, 3, 4]\\end{dependency}. You can use an empty array \"[]\" when this conjecture does not depend on other lemmas.\n",
-                "\n",
-                "More accurately, your response should obey the following format:\n",
-                "\n",
-                "\\begin{conjecture}Your new findings here\\end{conjecture}\n",
-                "\\begin{proof}Your proof of the conjecture above\\end{proof}\n",
-                "\\begin{dependency}An json array of related memory IDs of this conjecture\\end{dependency}",
-                "\n",
-                "Moreover, when you think the time is right that you are able to prove the original problem, you can simply state your proof inside \\begin{final_proof}\\end{final_proof}, and explicitly write down its dependency in \\begin{dependency}\\end{dependency}. In this case, you do not need to propose any new conjectures for this problem."
-            ))
-            + &context_prefix;
-
         return self
             .client
-            .comp(&prompt, &self.model, self.streaming, &self.reasoning_effort)
+            .comp(
+                &self.prompt(),
+                &self.model,
+                self.streaming,
+                &self.reasoning_effort,
+            )
             .await;
     }
 }
@@ -597,7 +1879,7 @@ pub struct SimpleReviewer {
 impl SimpleReviewer {
     pub fn new() -> Self {
         SimpleReviewer {
-            client: LMClient::new(),
+            client: LMClient::new().role("simple_reviewer"),
             model: String::new(),
             conjecture: String::new(),
             proof: String::new(),
@@ -611,6 +1893,10 @@ impl SimpleReviewer {
         self.model = model.into();
         self
     }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
     pub fn reviews(mut self, reviews: u8) -> Self {
         self.reviews = reviews;
         self
@@ -636,10 +1922,13 @@ impl SimpleReviewer {
         self
     }
 
-    pub async fn pverify(self: Arc<Self>) -> Option<String> {
+    pub async fn pverify(
+        self: Arc<Self>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
         // pessimistic verification for the given conjecture and proof
         // it will return a string of reviews if some flaws are found in the proof or conjecture
-        // or else it will return None when no problem is found
+        // or else it will return None when no problem is found, and an error when a reviewer
+        // could not be called since the proof was then not reviewed
         info!("Starting pverify with **{}** reviewers.", self.reviews);
         let pb = ProgressBar::new(self.reviews as u64);
         if let Ok(style) = ProgressStyle::with_template(
@@ -651,69 +1940,283 @@ impl SimpleReviewer {
         }
         pb.set_message("pverifying");
 
-        let mut tasks: JoinSet<Option<String>> = JoinSet::new();
-        for _ in 0..self.reviews {
+        let mut tasks: JoinSet<Result<String, Box<dyn std::error::Error + Send + Sync>>> =
+            JoinSet::new();
+        for i in 0..self.reviews {
             let n_reviewer = self.clone();
             let n_pb = pb.clone();
-            tasks.spawn(async move {
-                let res = match n_reviewer._process().await {
-                    Ok(s) => Some(s),
-                    Err(e) => {
-                        error!("Error Occured when reviewing: {}", e);
-                        None
-                    }
-                };
-                n_pb.inc(1);
-                res
-            });
+            let span = info_span!("review", reviewer = i);
+            tasks.spawn(
+                async move {
+                    let res = n_reviewer._process().await;
+                    n_pb.inc(1);
+                    res
+                }
+                .instrument(span),
+            );
         }
 
-        while let Ok(review) = tasks.join_next().await? {
-            if let Some(r) = review {
-                debug!("Collected one review: {}", &r);
-                if find_box(&r)? == "invalid" {
-                    info!("One reviewer found a flaw in the proof: {}", &r);
+        while let Some(joined) = tasks.join_next().await {
+            let review = match joined {
+                Ok(review) => review,
+                Err(e) => Err(e.into()),
+            };
+            let r = match review {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("Error Occured when reviewing: {}", e);
                     tasks.shutdown().await;
                     pb.finish();
-                    return Some(r);
+                    return Err(e);
                 }
+            };
+            debug!("Collected one review: {}", &r);
+            if find_box(&r).as_deref() == Some("invalid") {
+                info!("One reviewer found a flaw in the proof: {}", &r);
+                tasks.shutdown().await;
+                pb.finish();
+                return Ok(Some(r));
             }
         }
         pb.finish();
-        return None;
+        Ok(None)
+    }
+    fn prompt(&self) -> String {
+        fill(
+            SIMPLE_REVIEWER_PROMPT,
+            &[
+                ("conjecture", &self.conjecture),
+                ("proof", &self.proof),
+                (
+                    "context",
+                    &section(SIMPLE_REVIEWER_CONTEXT, "context", self.context.as_deref()),
+                ),
+            ],
+        )
     }
 }
 
+const SIMPLE_REVIEWER_PROMPT: &str = concat!(
+    "### Instruction\n",
+    "\n",
+    "You are an expert that is knowledgeable across all domains in math. Here you will be given a conjecture and a corresponding proof in math. You need to act as a reviewer of this proof, carefully examine and verify this proof.\n",
+    "\n",
+    "A valid proof must satisfy the following three conditions:\n",
+    "\n",
+    "1. **Correct**. There is no logical errors or calculation errors in the proof, and every theorems applied in the proof must accurately satisfy the required conditions.\n",
+    "2. **Complete**. The proof should contain every detailed intermediate steps in derivations or calculations.\n",
+    "3. **Rigorous**. Every statement in the proof must either come from detailed proofsteps or preliminaries or lemmas.\n",
+    "\n",
+    "Please state your verification result inside $\\boxed{}$ as $\\boxed{valid}$ or $\\boxed{invalid}$. You also need to include the rationale on your decision in your response.\n",
+    "\n",
+    "### Conjecture\n\n{{conjecture}}\n\n### Proof\n\n{{proof}}{{context}}",
+);
+const SIMPLE_REVIEWER_CONTEXT: &str = "\n\n### Context and History Explorations\n\nHere is a list of context that we have collected for this problem or our history findings during exploration. They serve as the background of the conjecture and proof and can be accepted without controversy as correct.\n\n{{context}}";
+
 #[async_trait::async_trait]
 impl Agent for SimpleReviewer {
     async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        let conjecture_proof = format!(
-            "### Conjecture\n\n{}\n\n### Proof\n\n{}",
-            &self.conjecture, &self.proof
-        );
-        let mut context_prefix = String::new();
-        if let Some(context) = &self.context {
-            context_prefix = format!(
-                "\n\n### Context and History Explorations\n\nHere is a list of context that we have collected for this problem or our history findings during exploration. They serve as the background of the conjecture and proof and can be accepted without controversy as correct.\n\n{}",
-                context
-            );
+        return self
+            .client
+            .comp(
+                &self.prompt(),
+                &self.model,
+                self.streaming,
+                &self.reasoning_effort,
+            )
+            .await;
+    }
+}
+
+#[derive(Clone)]
+pub struct DefinitionReviewer {
+    client: LMClient,
+    model: String,
+    definition: String,
+    context: Option<String>,
+    reasoning_effort: String,
+}
+
+impl DefinitionReviewer {
+    pub fn new() -> Self {
+        DefinitionReviewer {
+            client: LMClient::new().role("definition_reviewer"),
+            model: String::new(),
+            definition: String::new(),
+            context: None,
+            reasoning_effort: "medium".into(),
+        }
+    }
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
+    pub fn set_definition(&mut self, definition: impl Into<String>) -> &Self {
+        self.definition = definition.into();
+        self
+    }
+    pub fn set_context(&mut self, context: impl Into<String>) -> &Self {
+        self.context = Some(context.into());
+        self
+    }
+    pub fn reasoning_effort(mut self, effort: impl Into<String>) -> Self {
+        self.reasoning_effort = effort.into();
+        self
+    }
+
+    pub async fn check(&self) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        // definitions are not proved, they are only checked for well-formedness
+        // it will return the review if the definition is ill-formed, or else None
+        let r = self._process().await.inspect_err(|e| {
+            error!("Error Occured when reviewing a definition: {}", e);
+        })?;
+        if find_box(&r).as_deref() == Some("invalid") {
+            info!("Reviewer found the definition ill-formed: {}", &r);
+            Ok(Some(r))
+        } else {
+            Ok(None)
         }
-        let prompt = concat!(
-             "### Instruction\n",
-             "\n",
-             "You are an expert that is knowledgeable across all domains in math. Here you will be given a conjecture and a corresponding proof in math. You need to act as a reviewer of this proof, carefully examine and verify this proof.\n",
-             "\n",
-             "A valid proof must satisfy the following three conditions:\n",
-             "\n",
-             "1. **Correct**. There is no logical errors or calculation errors in the proof, and every theorems applied in the proof must accurately satisfy the required conditions.\n",
-             "2. **Complete**. The proof should contain every detailed intermediate steps in derivations or calculations.\n",
-             "3. **Rigorous**. Every statement in the proof must either come from detailed proofsteps or preliminaries or lemmas.\n",
-             "\n",
-             "Please state your verification result inside $\\boxed{}$ as $\\boxed{valid}$ or $\\boxed{invalid}$. You also need to include the rationale on your decision in your response.\n",
-             "\n").to_string() + &conjecture_proof + &context_prefix;
+    }
+    fn prompt(&self) -> String {
+        fill(
+            DEFINITION_REVIEWER_PROMPT,
+            &[
+                ("definition", &self.definition),
+                (
+                    "context",
+                    &section(
+                        DEFINITION_REVIEWER_CONTEXT,
+                        "context",
+                        self.context.as_deref(),
+                    ),
+                ),
+            ],
+        )
+    }
+}
+
+const DEFINITION_REVIEWER_PROMPT: &str = concat!(
+    "### Instruction\n",
+    "\n",
+    "You are an expert that is knowledgeable across all domains in math. Here you will be given a mathematical definition proposed in our research. Definitions are not proved, but you need to act as a reviewer and check whether this definition is well-formed:\n",
+    "\n",
+    "1. **Grounded**. Every object, symbol and notion used in the definition is either standard or defined in the context.\n",
+    "2. **Well-defined**. The defined object exists and is uniquely determined, and it does not depend on arbitrary choices unless they are explicitly part of the definition.\n",
+    "3. **Non-circular**. The definition does not refer to the notion being defined.\n",
+    "\n",
+    "Please state your verification result inside $\\boxed{}$ as $\\boxed{valid}$ or $\\boxed{invalid}$. You also need to include the rationale on your decision in your response.\n",
+    "\n",
+    "### Definition\n",
+    "\n",
+    "{{definition}}{{context}}",
+);
+const DEFINITION_REVIEWER_CONTEXT: &str = "\n\n### Context and History Explorations\n\nHere is a list of context that we have collected for this problem or our history findings during exploration. They serve as the background of the definition and can be accepted without controversy as correct.\n\n{{context}}";
+
+#[async_trait::async_trait]
+impl Agent for DefinitionReviewer {
+    async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         return self
             .client
-            .comp(&prompt, &self.model, self.streaming, &self.reasoning_effort)
+            .comp(&self.prompt(), &self.model, false, &self.reasoning_effort)
+            .await;
+    }
+}
+
+#[derive(Clone)]
+pub struct Skeptic {
+    client: LMClient,
+    model: String,
+    conjecture: String,
+    context: Option<String>,
+    reasoning_effort: String,
+}
+
+impl Skeptic {
+    pub fn new() -> Self {
+        Skeptic {
+            client: LMClient::new().role("skeptic"),
+            model: String::new(),
+            conjecture: String::new(),
+            context: None,
+            reasoning_effort: "low".into(),
+        }
+    }
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
+    pub fn set_conjecture(&mut self, conjecture: impl Into<String>) -> &Self {
+        self.conjecture = conjecture.into();
+        self
+    }
+    pub fn set_context(&mut self, context: impl Into<String>) -> &Self {
+        self.context = Some(context.into());
+        self
+    }
+
+    pub async fn refute(&self) -> Option<String> {
+        // a cheap attack on the statement alone before any proof is reviewed
+        // it will return the counterexample if the conjecture is false, or else None
+        match self._process().await {
+            Ok(r) => {
+                if find_box(&r).as_deref() == Some("false") {
+                    info!("Skeptic found the conjecture false: {}", &r);
+                    Some(extract_component(&r, "counterexample").unwrap_or(r))
+                } else {
+                    None
+                }
+            }
+            Err(e) => {
+                error!("Error Occured when attacking a conjecture: {}", e);
+                None
+            }
+        }
+    }
+    fn prompt(&self) -> String {
+        fill(
+            SKEPTIC_PROMPT,
+            &[
+                ("conjecture", &self.conjecture),
+                (
+                    "context",
+                    &section(SKEPTIC_CONTEXT, "context", self.context.as_deref()),
+                ),
+            ],
+        )
+    }
+}
+
+const SKEPTIC_PROMPT: &str = concat!(
+    "### Instruction\n",
+    "\n",
+    "You are a skeptical expert that is knowledgeable across all domains in math. Here you will be given a new conjecture proposed in our research, without its proof. Before we spend effort on verifying its proof, you need to try to falsify the statement itself:\n",
+    "\n",
+    "1. **Small cases**. Check the statement on the smallest and simplest instances, such as small parameters, low dimensions and trivial objects.\n",
+    "2. **Edge conditions**. Check the boundary cases of every hypothesis and every quantifier, such as empty sets, zero, equality cases and degenerate objects.\n",
+    "3. **Missing hypotheses**. Check whether the statement silently relies on an assumption that is not stated, such that an object violating it breaks the claim.\n",
+    "\n",
+    "Only claim that the conjecture is false when you have found an explicit and checkable counterexample. In this case, wrap the counterexample and its verification inside \\begin{counterexample}\\end{counterexample}, and write down $\\boxed{false}$ at the end of your response. Otherwise write down $\\boxed{plausible}$ at the end of your response.\n",
+    "\n",
+    "### Conjecture\n",
+    "\n",
+    "{{conjecture}}{{context}}",
+);
+const SKEPTIC_CONTEXT: &str = "\n\n### Cited Results\n\nThe conjecture relies on the following results, which can be accepted as correct.\n\n{{context}}";
+
+#[async_trait::async_trait]
+impl Agent for Skeptic {
+    async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        return self
+            .client
+            .comp(&self.prompt(), &self.model, false, &self.reasoning_effort)
             .await;
     }
 }
@@ -748,10 +2251,48 @@ pub struct ProgressiveReviewResult {
     pub api_calls: u8,
 }
 
+const PROGRESSIVE_REVIEWER_PROMPT: &str = concat!(
+    "You are an assistant highly proficient in mathematics. The user will provide a math problem together with its proposed solution, and your task is to verify the correctness of that solution according to the given instruction.\n",
+    "Here is a math problem and a candidate solution of it, and you need to verify the correctness of this solution. Please check each of the following:\n\n",
+    "1. The provided content is indeed a math problem and its corresponding solution, rather than unrelated material supplied by mistake.\n",
+    "2. The solution actually derives the conclusion required by the original problem.\n",
+    "3. Every step of calculation and formula derivation in the solution is correct.\n",
+    "4. The hypotheses (conditions) and conclusions of any theorems used are correctly matched and applied.\n",
+    "5. The solution relies only on the conditions given in the problem and does not introduce any additional assumptions to obtain the conclusion.\n\n",
+    "Consistency and error-severity policy (important):\n",
+    "- If only minor, easily fixable issues exist (e.g., small algebraic slips later corrected, notational typos, superficial formatting), treat the solution as correct overall but briefly note such issues.\n",
+    "- If there is any critical error that undermines correctness (e.g., invalid step, wrong theorem usage without required conditions, uncorrected calculation error leading to a wrong result), treat the solution as incorrect.\n\n",
+    "Response requirements: If the solution is correct overall (possibly with minor issues), reply with `<verification>true</verification>` and briefly list minor issues if any.",
+    " If the solution is incorrect, reply with `<verification>false</verification>` followed by a concise description of the most harmful error.",
+    " Do not include any restatement of the entire solution or problem.\n\n",
+    "<problem>{{problem}}</problem>\n\n",
+    "<answer>{{answer}}</answer>{{context}}",
+);
+const PROGRESSIVE_REVIEWER_CHUNK_PROMPT: &str = concat!(
+    "You are an assistant highly proficient in mathematics. The user will provide a math problem together with its proposed solution, and your task is to verify the correctness of that solution according to the given instruction.\n",
+    "We provide the original problem and the complete proposed solution for full context. ",
+    "Then we provide a specific chunk from the solution for focused checking. ",
+    "Your task: Check ONLY the given chunk for errors while considering the overall context.\n\n",
+    "Checklist:\n",
+    "1. The chunk's reasoning and calculations adhere to mathematical correctness.\n",
+    "2. Any theorems used in the chunk match their hypotheses and conclusions.\n",
+    "3. The chunk does not rely on assumptions not justified by the problem or earlier proven steps.\n\n",
+    "Consistency and error-severity policy (important):\n",
+    "- If only minor, easily fixable issues exist (e.g., small algebraic slips later corrected, notational typos, superficial formatting), treat the chunk as correct overall but briefly note such issues.\n",
+    "- If there is any critical error that undermines correctness in this chunk (e.g., invalid step, wrong theorem usage without required conditions), treat the chunk as incorrect.\n\n",
+    "Response requirements: If the chunk is correct overall (possibly with minor issues), reply with `<verification>true</verification>` and briefly list minor issues if any. ",
+    "If the chunk is incorrect, reply with `<verification>false</verification>` followed by a concise description of the most harmful error in the proof that you found in the chunk.\n\n",
+    "<problem>{{problem}}</problem>\n\n",
+    "<full_answer>{{answer}}</full_answer>\n\n",
+    "<chunk_index>{{chunk_index}}</chunk_index>\n",
+    "<chunk>{{chunk}}</chunk>{{context}}",
+);
+const PROGRESSIVE_REVIEWER_CONTEXT: &str = "\n\n### Context and History Explorations\n\nHere is a list of context that we have collected for this problem or our history findings during exploration. They serve as the background of the conjecture and proof and can be accepted without controversy as correct.\n\n{{context}}";
+
 impl ProgressiveReviewer {
     pub fn new() -> Self {
         ProgressiveReviewer {
-            client: LMClient::new(),
+            client: LMClient::new().role("progressive_reviewer"),
             model: String::new(),
             conjecture: String::new(),
             proof: String::new(),
@@ -765,6 +2306,10 @@ impl ProgressiveReviewer {
         self.model = model.into();
         self
     }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
     pub fn set_conjecture(&mut self, conjecture: impl Into<String>) -> &Self {
         self.conjecture = conjecture.into();
         self
@@ -811,21 +2356,44 @@ impl ProgressiveReviewer {
         self.min_chunk_size.max(approx_length)
     }
 
-    pub async fn verify(&self) -> ProgressiveReviewResult {
+    fn prompt(&self, chunk: Option<(usize, &str)>) -> String {
+        let context = section(
+            PROGRESSIVE_REVIEWER_CONTEXT,
+            "context",
+            self.context.as_deref(),
+        );
+        match chunk {
+            None => fill(
+                PROGRESSIVE_REVIEWER_PROMPT,
+                &[
+                    ("problem", &self.conjecture),
+                    ("answer", &self.proof),
+                    ("context", &context),
+                ],
+            ),
+            Some((chunk_id, chunk)) => fill(
+                PROGRESSIVE_REVIEWER_CHUNK_PROMPT,
+                &[
+                    ("problem", &self.conjecture),
+                    ("answer", &self.proof),
+                    ("chunk_index", &chunk_id.to_string()),
+                    ("chunk", chunk),
+                    ("context", &context),
+                ],
+            ),
+        }
+    }
+
+    pub async fn verify(
+        &self,
+    ) -> Result<ProgressiveReviewResult, Box<dyn std::error::Error + Send + Sync>> {
+        // A chunk that could not be reviewed fails the verification with its error
         info!(
             "Starting progressive verification (max_iters={})",
             self.max_iters
         );
         let mut api_calls: u8 = 0;
 
-        let mut context_prefix = String::new();
-        if let Some(context) = &self.context {
-            context_prefix = format!(
-                "\n\n### Context and History Explorations\n\nHere is a list of context that we have collected for this problem or our history findings during exploration. They serve as the background of the conjecture and proof and can be accepted without controversy as correct.\n\n{}",
-                context
-            );
-        }
-
         for iteration in 0..self.max_iters {
             let chunk_length = self.chunk_length_for_iteration(&self.proof, iteration);
             let chunks = Self::split_into_chunks(&self.proof, chunk_length);
@@ -844,60 +2412,17 @@ impl ProgressiveReviewer {
                 let client = self.client.clone();
                 let model = self.model.clone();
                 let reasoning_effort = self.reasoning_effort.clone();
-                let conjecture = self.conjecture.clone();
-                let full_proof = self.proof.clone();
-                let context_section = context_prefix.clone();
-
+                // The first iteration reviews the whole proof, later ones review single chunks
                 let prompt = if iteration == 0 {
-                    // Standard prompt for whole proof
-                    format!(
-                        concat!(
-                            "You are an assistant highly proficient in mathematics. The user will provide a math problem together with its proposed solution, and your task is to verify the correctness of that solution according to the given instruction.\n",
-                            "Here is a math problem and a candidate solution of it, and you need to verify the correctness of this solution. Please check each of the following:\n\n",
-                            "1. The provided content is indeed a math problem and its corresponding solution, rather than unrelated material supplied by mistake.\n",
-                            "2. The solution actually derives the conclusion required by the original problem.\n",
-                            "3. Every step of calculation and formula derivation in the solution is correct.\n",
-                            "4. The hypotheses (conditions) and conclusions of any theorems used are correctly matched and applied.\n",
-                            "5. The solution relies only on the conditions given in the problem and does not introduce any additional assumptions to obtain the conclusion.\n\n",
-                            "Consistency and error-severity policy (important):\n",
-                            "- If only minor, easily fixable issues exist (e.g., small algebraic slips later corrected, notational typos, superficial formatting), treat the solution as correct overall but briefly note such issues.\n",
-                            "- If there is any critical error that undermines correctness (e.g., invalid step, wrong theorem usage without required conditions, uncorrected calculation error leading to a wrong result), treat the solution as incorrect.\n\n",
-                            "Response requirements: If the solution is correct overall (possibly with minor issues), reply with `<verification>true</verification>` and briefly list minor issues if any.",
-                            " If the solution is incorrect, reply with `<verification>false</verification>` followed by a concise description of the most harmful error.",
-                            " Do not include any restatement of the entire solution or problem.\n\n",
-                            "<problem>{}</problem>\n\n",
-                            "<answer>{}</answer>{}",
-                        ),
-                        conjecture, full_proof, context_section
-                    )
+                    self.prompt(None)
                 } else {
-                    // Chunk prompt
-                    format!(
-                        concat!(
-                            "You are an assistant highly proficient in mathematics. The user will provide a math problem together with its proposed solution, and your task is to verify the correctness of that solution according to the given instruction.\n",
-                            "We provide the original problem and the complete proposed solution for full context. ",
-                            "Then we provide a specific chunk from the solution for focused checking. ",
-                            "Your task: Check ONLY the given chunk for errors while considering the overall context.\n\n",
-                            "Checklist:\n",
-                            "1. The chunk's reasoning and calculations adhere to mathematical correctness.\n",
-                            "2. Any theorems used in the chunk match their hypotheses and conclusions.\n",
-                            "3. The chunk does not rely on assumptions not justified by the problem or earlier proven steps.\n\n",
-                            "Consistency and error-severity policy (important):\n",
-                            "- If only minor, easily fixable issues exist (e.g., small algebraic slips later corrected, notational typos, superficial formatting), treat the chunk as correct overall but briefly note such issues.\n",
-                            "- If there is any critical error that undermines correctness in this chunk (e.g., invalid step, wrong theorem usage without required conditions), treat the chunk as incorrect.\n\n",
-                            "Response requirements: If the chunk is correct overall (possibly with minor issues), reply with `<verification>true</verification>` and briefly list minor issues if any. ",
-                            "If the chunk is incorrect, reply with `<verification>false</verification>` followed by a concise description of the most harmful error in the proof that you found in the chunk.\n\n",
-                            "<problem>{}</problem>\n\n",
-                            "<full_answer>{}</full_answer>\n\n",
-                            "<chunk_index>{}</chunk_index>\n",
-                            "<chunk>{}</chunk>{}",
-                        ),
-                        conjecture, full_proof, chunk_id, chunk, context_section
-                    )
+                    self.prompt(Some((chunk_id, &chunk)))
                 };
 
+                let span = info_span!("review_chunk", iteration, chunk = chunk_id);
                 tasks.spawn(
-                    async move { client.comp(&prompt, &model, false, &reasoning_effort).await },
+                    async move { client.comp(&prompt, &model, false, &reasoning_effort).await }
+                        .instrument(span),
                 );
             }
 
@@ -923,9 +2448,13 @@ impl ProgressiveReviewer {
                     }
                     Ok(Err(e)) => {
                         error!("Error during verification: {}", e);
+                        tasks.shutdown().await;
+                        return Err(e);
                     }
                     Err(e) => {
                         error!("Task join error: {}", e);
+                        tasks.shutdown().await;
+                        return Err(e.into());
                     }
                 }
             }
@@ -936,10 +2465,10 @@ impl ProgressiveReviewer {
                     "Verification failed at iteration {}: {}",
                     iteration, error_msg
                 );
-                return ProgressiveReviewResult {
+                return Ok(ProgressiveReviewResult {
                     review: Some(error_msg),
                     api_calls,
-                };
+                });
             }
 
             // If all chunks passed, proceed to next iteration (finer granularity)
@@ -947,13 +2476,14 @@ impl ProgressiveReviewer {
         }
 
         info!("Progressive verification passed all iterations.");
-        ProgressiveReviewResult {
+        Ok(ProgressiveReviewResult {
             review: None,
             api_calls,
-        }
+        })
     }
 }
 
+#[derive(Clone)]
 pub struct Refiner {
     client: LMClient,
     model: String,
@@ -968,7 +2498,7 @@ pub struct Refiner {
 impl Refiner {
     pub fn new() -> Self {
         Refiner {
-            client: LMClient::new(),
+            client: LMClient::new().role("refiner"),
             model: String::new(),
             conjecture: String::new(),
             proof: String::new(),
@@ -982,6 +2512,10 @@ impl Refiner {
         self.model = model.into();
         self
     }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
     pub fn streaming(mut self, streaming: bool) -> Self {
         self.streaming = streaming;
         self
@@ -1006,34 +2540,45 @@ impl Refiner {
         self.reasoning_effort = effort.into();
         self
     }
+    fn prompt(&self) -> String {
+        fill(
+            REFINER_PROMPT,
+            &[
+                ("conjecture", &self.conjecture),
+                ("proof", &self.proof),
+                ("review", &self.review),
+                (
+                    "context",
+                    &section(REFINER_CONTEXT, "context", self.context.as_deref()),
+                ),
+            ],
+        )
+    }
 }
 
+const REFINER_PROMPT: &str = concat!(
+    "### Instruction\n",
+    "\n",
+    "You are an expert that is knowledgeable across all domains in math. This time you are asked to help with frontier math research. We have proposed a new conjecture, and tried to prove it. However, one reviewer have found some flaws in our proof. You need to help us with our research project by:\n",
+    "\n",
+    "1. Please try to refine or even completely rewrite the proof so that it can be **correct**, **complete** and **rigorous**. You should wrap your new proof inside latex environment as \\begin{proof}\\end{proof} in your response. Once you have done this refinement, you should write down a \"\\boxed{true}\" at the end of your response.\n",
+    "2. And if you believe this conjecture itself is not true, please state the opposite of this conjecture inside \\begin{conjecture}\\end{conjecture}, and your rationales or proofs of this judgement inside \\begin{proof}\\end{proof}. Finally you should write down a \"\\boxed{false}\" at the end of your response.\n",
+    "\n",
+    "### Conjecture\n\n{{conjecture}}\n\n### Proof\n\n{{proof}}\n\n### Review\n\n{{review}}{{context}}",
+);
+const REFINER_CONTEXT: &str = "\n\n### Context and History Explorations\n\nHere is a list of context that we have collected for this problem or our history findings during exploration. They serve as the background of the conjecture and proof, and can be accepted without controversy as correct.\n\n{{context}}";
+
 #[async_trait::async_trait]
 impl Agent for Refiner {
     async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        let conjecture_proof_review = format!(
-            "### Conjecture\n\n{}\n\n### Proof\n\n{}\n\n### Review\n\n{}",
-            &self.conjecture, &self.proof, &self.review
-        );
-        let mut context_prefix = String::new();
-        if let Some(context) = &self.context {
-            context_prefix = format!(
-                "\n\n### Context and History Explorations\n\nHere is a list of context that we have collected for this problem or our history findings during exploration. They serve as the background of the conjecture and proof, and can be accepted without controversy as correct.\n\n{}",
-                context
-            );
-        }
-        let prompt = concat!(
-            "### Instruction\n",
-            "\n",
-            "You are an expert that is knowledgeable across all domains in math. This time you are asked to help with frontier math research. We have proposed a new conjecture, and tried to prove it. However, one reviewer have found some flaws in our proof. You need to help us with our research project by:\n",
-            "\n",
-            "1. Please try to refine or even completely rewrite the proof so that it can be **correct**, **complete** and **rigorous**. You should wrap your new proof inside latex environment as \\begin{proof}\\end{proof} in your response. Once you have done this refinement, you should write down a \"\\boxed{true}\" at the end of your response.\n",
-            "2. And if you believe this conjecture itself is not true, please state the opposite of this conjecture inside \\begin{conjecture}\\end{conjecture}, and your rationales or proofs of this judgement inside \\begin{proof}\\end{proof}. Finally you should write down a \"\\boxed{false}\" at the end of your response.\n",
-            "\n"
-        ).to_string() + &conjecture_proof_review + &context_prefix;
         return self
             .client
-            .comp(&prompt, &self.model, self.streaming, &self.reasoning_effort)
+            .comp(
+                &self.prompt(),
+                &self.model,
+                self.streaming,
+                &self.reasoning_effort,
+            )
             .await;
     }
 }
@@ -1042,15 +2587,17 @@ pub struct Formatter {
     client: LMClient,
     model: String,
     content: String,
+    kind: Option<String>,
     reasoning_effort: String,
 }
 
 impl Formatter {
     pub fn new() -> Self {
         Formatter {
-            client: LMClient::new(),
+            client: LMClient::new().role("formatter"),
             model: String::new(),
             content: String::new(),
+            kind: None,
             reasoning_effort: "medium".into(),
         }
     }
@@ -1058,32 +2605,57 @@ impl Formatter {
         self.model = model.into();
         self
     }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
     pub fn content(mut self, content: impl Into<String>) -> Self {
         self.content = content.into();
         self
     }
+    /// What the contents are, e.g. "the statement of a conjecture that was refuted"
+    pub fn kind(mut self, kind: impl Into<String>) -> Self {
+        self.kind = Some(kind.into());
+        self
+    }
     pub fn reasoning_effort(mut self, effort: impl Into<String>) -> Self {
         self.reasoning_effort = effort.into();
         self
     }
+    fn prompt(&self) -> String {
+        fill(
+            FORMATTER_PROMPT,
+            &[
+                (
+                    "kind",
+                    &section(FORMATTER_KIND, "kind", self.kind.as_deref()),
+                ),
+                ("contents", &self.content),
+            ],
+        )
+    }
 }
 
+const FORMATTER_PROMPT: &str = concat!(
+    "Please help me rewrite these math related contents into standard markdown format for preview. You should obey the following instructions when completing this task:\n",
+    "\n",
+    "1. DO NOT modify or alter the original meaning in these contents.\n",
+    "2. You should not use headings in the reformatted contents.\n",
+    "3. Each math formula should be wrapped in dollars like $ $ for inline formula and $$ $$ for multiline one.\n",
+    "4. You should wrap the reformatted contents inside latex environment as \\begin{contents}reformatted contents here\\end{contents}\n",
+    "\n",
+    "Here is the original contents:\n",
+    "\n",
+    "{{kind}}\\begin{contents}{{contents}}\\end{contents}",
+);
+const FORMATTER_KIND: &str = "These contents are {{kind}}. Keep them recognizable as such, for example do not present a refuted statement as a valid result.\n\n";
+
 #[async_trait::async_trait]
 impl Agent for Formatter {
     async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        let prompt = concat!(
-            "Please help me rewrite these math related contents into standard markdown format for preview. You should obey the following instructions when completing this task:\n",
-            "\n",
-            "1. DO NOT modify or alter the original meaning in these contents.\n",
-            "2. You should not use headings in the reformatted contents.\n",
-            "3. Each math formula should be wrapped in dollars like $ $ for inline formula and $$ $$ for multiline one.\n",
-            "4. You should wrap the reformatted contents inside latex environment as \\begin{contents}reformatted contents here\\end{contents}\n",
-            "\n",
-            "Here is the original contents:\n",
-            "\n").to_string() + &format!("\\begin{{contents}}{}\\end{{contents}}", self.content);
         return self
             .client
-            .comp(&prompt, &self.model, false, &self.reasoning_effort)
+            .comp(&self.prompt(), &self.model, false, &self.reasoning_effort)
             .await;
     }
 }
@@ -1098,7 +2670,7 @@ pub struct ContextGenerator {
 impl ContextGenerator {
     pub fn new() -> Self {
         ContextGenerator {
-            client: LMClient::new(),
+            client: LMClient::new().role("context_generator"),
             model: String::new(),
             problem: String::new(),
             reasoning_effort: "medium".into(),
@@ -1108,6 +2680,10 @@ impl ContextGenerator {
         self.model = model.into();
         self
     }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
     pub fn problem(mut self, problem: impl Into<String>) -> Self {
         self.problem = problem.into();
         self
@@ -1116,30 +2692,32 @@ impl ContextGenerator {
         self.reasoning_effort = effort.into();
         self
     }
+    fn prompt(&self) -> String {
+        fill(CONTEXT_GENERATOR_PROMPT, &[("problem", &self.problem)])
+    }
 }
 
+const CONTEXT_GENERATOR_PROMPT: &str = concat!(
+    "You are an expert in mathematics. I will provide you with a research problem.\n",
+    "Your task is to provide a brief background introduction and context for this problem.\n",
+    "This should include:\n",
+    "1. Definitions of key mathematical objects and symbols used in the problem.\n",
+    "2. Relevant theorems or known results that are closely related to this problem.\n",
+    "3. Any standard techniques or methods often used in this domain.\n",
+    "\n",
+    "Please keep the response informative, accurate, and relevant to the problem.\n",
+    "Format the output in standard Markdown with LaTeX math support.\n",
+    "\n",
+    "The problem is:\n",
+    "\\begin{problem}{{problem}}\\end{problem}",
+);
+
 #[async_trait::async_trait]
 impl Agent for ContextGenerator {
     async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        let prompt = concat!(
-            "You are an expert in mathematics. I will provide you with a research problem.\n",
-            "Your task is to provide a brief background introduction and context for this problem.\n",
-            "This should include:\n",
-            "1. Definitions of key mathematical objects and symbols used in the problem.\n",
-            "2. Relevant theorems or known results that are closely related to this problem.\n",
-            "3. Any standard techniques or methods often used in this domain.\n",
-            "\n",
-            "Please keep the response informative, accurate, and relevant to the problem.\n",
-            "Format the output in standard Markdown with LaTeX math support.\n",
-            "\n",
-            "The problem is:\n"
-        )
-        .to_string()
-            + &format!("\\begin{{problem}}{}\\end{{problem}}", self.problem);
-
         return self
             .client
-            .comp(&prompt, &self.model, false, &self.reasoning_effort)
+            .comp(&self.prompt(), &self.model, false, &self.reasoning_effort)
             .await;
     }
 }
@@ -1155,7 +2733,7 @@ pub struct ProofSummarizer {
 impl ProofSummarizer {
     pub fn new() -> Self {
         ProofSummarizer {
-            client: LMClient::new(),
+            client: LMClient::new().role("proof_summarizer"),
             model: String::new(),
             conjecture: String::new(),
             proof: String::new(),
@@ -1166,6 +2744,10 @@ impl ProofSummarizer {
         self.model = model.into();
         self
     }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
     pub fn conjecture(mut self, conjecture: impl Into<String>) -> Self {
         self.conjecture = conjecture.into();
         self
@@ -1178,31 +2760,928 @@ impl ProofSummarizer {
         self.reasoning_effort = effort.into();
         self
     }
+    fn prompt(&self) -> String {
+        fill(
+            PROOF_SUMMARIZER_PROMPT,
+            &[("conjecture", &self.conjecture), ("proof", &self.proof)],
+        )
+    }
 }
 
+const PROOF_SUMMARIZER_PROMPT: &str = concat!(
+    "You will be given a mathematical conjecture and its proof.\n",
+    "Your task is to carefully read and understand the proof, then produce a clear and concise summary that includes:\n",
+    "1. **Overall Overview** – a brief description of the main idea and approach of the proof.\n",
+    "2. **Key Steps** – the essential logical steps in the proof, listed or described in order.\n",
+    "3. **Main Ideas / Techniques** – important mathematical concepts, techniques, or strategies applied in the proof.\n",
+    "\n",
+    "Do not rewrite the full proof or include excessive details.\n",
+    "Focus on extracting and condensing the essence of the proof’s reasoning.\n",
+    "Present the final result strictly inside the following tags:\n",
+    "```\n",
+    "\\begin{summary}\n",
+    "[Your concise summary here]\n",
+    "\\end{summary}\n",
+    "```\n",
+    "Ensure that the summary is self-contained and understandable without referencing the original text.\n",
+    "\n",
+    "\\begin{conjecture}{{conjecture}}\\end{conjecture}\n\\begin{proof}{{proof}}\\end{proof}",
+);
+
 #[async_trait::async_trait]
 impl Agent for ProofSummarizer {
     async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        let prompt = concat!(
-            "You will be given a mathematical conjecture and its proof.\n",
-            "Your task is to carefully read and understand the proof, then produce a clear and concise summary that includes:\n",
-            "1. **Overall Overview** – a brief description of the main idea and approach of the proof.\n",
-            "2. **Key Steps** – the essential logical steps in the proof, listed or described in order.\n",
-            "3. **Main Ideas / Techniques** – important mathematical concepts, techniques, or strategies applied in the proof.\n",
-            "\n",
-            "Do not rewrite the full proof or include excessive details.\n",
-            "Focus on extracting and condensing the essence of the proof’s reasoning.\n",
-            "Present the final result strictly inside the following tags:\n",
-            "```\n",
-            "\\begin{summary}\n",
-            "[Your concise summary here]\n",
-            "\\end{summary}\n",
-            "```\n",
-            "Ensure that the summary is self-contained and understandable without referencing the original text.\n",
-            "\n").to_string() + &format!("\\begin{{conjecture}}{}\\end{{conjecture}}\n\\begin{{proof}}{}\\end{{proof}}", self.conjecture, self.proof);
         return self
             .client
-            .comp(&prompt, &self.model, false, &self.reasoning_effort)
+            .comp(&self.prompt(), &self.model, false, &self.reasoning_effort)
+            .await;
+    }
+}
+
+#[derive(Clone)]
+pub struct Embedder {
+    client: LMClient,
+    model: String,
+}
+
+impl Embedder {
+    pub fn new() -> Self {
+        Embedder {
+            client: LMClient::new().role("embedder"),
+            model: String::new(),
+        }
+    }
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
+    /// Embed all inputs in a single request, in the order they were given
+    pub async fn embed(
+        &self,
+        inputs: &[String],
+    ) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error + Send + Sync>> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.client.embeddings(inputs, &self.model).await
+    }
+}
+
+pub struct Consolidator {
+    client: LMClient,
+    model: String,
+    problem: String,
+    memory: String,
+    previous_digest: Option<String>,
+    reasoning_effort: String,
+}
+
+impl Consolidator {
+    pub fn new() -> Self {
+        Consolidator {
+            client: LMClient::new().role("consolidator"),
+            model: String::new(),
+            problem: String::new(),
+            memory: String::new(),
+            previous_digest: None,
+            reasoning_effort: "medium".into(),
+        }
+    }
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
+    pub fn problem(mut self, problem: impl Into<String>) -> Self {
+        self.problem = problem.into();
+        self
+    }
+    pub fn memory(mut self, memory: impl Into<String>) -> Self {
+        self.memory = memory.into();
+        self
+    }
+    pub fn previous_digest(mut self, digest: Option<String>) -> Self {
+        self.previous_digest = digest;
+        self
+    }
+    pub fn reasoning_effort(mut self, effort: impl Into<String>) -> Self {
+        self.reasoning_effort = effort.into();
+        self
+    }
+    fn prompt(&self) -> String {
+        let previous_digest = section(
+            CONSOLIDATOR_PREVIOUS_DIGEST,
+            "previous_digest",
+            self.previous_digest.as_deref(),
+        );
+        fill(
+            CONSOLIDATOR_PROMPT,
+            &[
+                ("problem", &self.problem),
+                ("results", &self.memory),
+                ("previous_digest", &previous_digest),
+            ],
+        )
+    }
+}
+
+const CONSOLIDATOR_PROMPT: &str = concat!(
+    "### Instruction\n",
+    "\n",
+    "You are an expert that is knowledgeable across all domains in math. We are working on a frontier math research problem, and have collected many intermediate results during exploration. Your task is to consolidate them into a structured research digest, which will replace the full list of results when we plan the next explorations.\n",
+    "\n",
+    "The digest should contain the following sections:\n",
+    "\n",
+    "1. **Main Proven Tools**: the most useful solved results, each with its exact memory ID and a precise restatement, so that they can still be cited as dependencies.\n",
+    "2. **Open Subgoals**: statements that still need to be proved to solve the problem.\n",
+    "3. **Dead Ends**: refuted conjectures and failed approaches that should not be repeated.\n",
+    "4. **Most Promising Direction**: the approach that is most likely to solve the problem, and the next steps along it.\n",
+    "\n",
+    "Only cite memory IDs that appear in the results below. Wrap the whole digest inside \\begin{digest}\\end{digest}.\n",
+    "\n",
+    "### Problem\n",
+    "\n",
+    "{{problem}}\n\n### Collected Results\n\n{{results}}{{previous_digest}}",
+);
+const CONSOLIDATOR_PREVIOUS_DIGEST: &str = "\n\n### Previous Digest\n\nUpdate this digest with the new findings instead of starting over.\n\n{{previous_digest}}";
+
+#[async_trait::async_trait]
+impl Agent for Consolidator {
+    async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        return self
+            .client
+            .comp(&self.prompt(), &self.model, false, &self.reasoning_effort)
+            .await;
+    }
+}
+
+#[derive(Clone)]
+pub struct Screener {
+    client: LMClient,
+    model: String,
+    problem: String,
+    context: Option<String>,
+    candidate: String,
+    reasoning_effort: String,
+}
+
+impl Screener {
+    pub fn new() -> Self {
+        Screener {
+            client: LMClient::new().role("screener"),
+            model: String::new(),
+            problem: String::new(),
+            context: None,
+            candidate: String::new(),
+            reasoning_effort: "low".into(),
+        }
+    }
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
+    pub fn problem(mut self, problem: impl Into<String>) -> Self {
+        self.problem = problem.into();
+        self
+    }
+    pub fn context(mut self, context: Option<String>) -> Self {
+        self.context = context;
+        self
+    }
+    pub fn candidate(mut self, candidate: impl Into<String>) -> Self {
+        self.candidate = candidate.into();
+        self
+    }
+    pub async fn score(&self) -> f64 {
+        // a quick rating of a candidate exploration in [0, 10], failures are rated 0
+        match self._process().await {
+            Ok(r) => find_box(&r)
+                .and_then(|score| score.trim().parse::<f64>().ok())
+                .map_or(0.0, |score| score.clamp(0.0, 10.0)),
+            Err(e) => {
+                error!("Error Occured when screening a candidate: {}", e);
+                0.0
+            }
+        }
+    }
+    fn prompt(&self) -> String {
+        fill(
+            SCREENER_PROMPT,
+            &[
+                ("problem", &self.problem),
+                ("candidate", &self.candidate),
+                (
+                    "context",
+                    &section(SCREENER_CONTEXT, "context", self.context.as_deref()),
+                ),
+            ],
+        )
+    }
+}
+
+const SCREENER_PROMPT: &str = concat!(
+    "### Instruction\n",
+    "\n",
+    "You are an expert that is knowledgeable across all domains in math. We are working on a frontier math research problem, and several candidate findings were proposed in parallel. Please quickly screen the following candidate and rate how promising it is, considering whether it is plausibly correct, whether it is novel, and how much it helps with solving the problem. You do not need to verify the proof in full detail.\n",
+    "\n",
+    "Give your rating as a number from 0 to 10 at the end of your response, wrapped in \\boxed{}.\n",
+    "\n",
+    "### Problem\n",
+    "\n",
+    "{{problem}}\n\n### Candidate\n\n{{candidate}}{{context}}",
+);
+const SCREENER_CONTEXT: &str = "\n\n### Cited Results\n\nThe candidate relies on the following results, which can be accepted as correct.\n\n{{context}}";
+
+#[async_trait::async_trait]
+impl Agent for Screener {
+    async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        return self
+            .client
+            .comp(&self.prompt(), &self.model, false, &self.reasoning_effort)
+            .await;
+    }
+}
+
+pub struct Planner {
+    client: LMClient,
+    model: String,
+    problem: String,
+    context: Option<String>,
+    revision: Option<String>,
+    reasoning_effort: String,
+}
+
+impl Planner {
+    pub fn new() -> Self {
+        Planner {
+            client: LMClient::new().role("planner"),
+            model: String::new(),
+            problem: String::new(),
+            context: None,
+            revision: None,
+            reasoning_effort: "medium".into(),
+        }
+    }
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
+    pub fn problem(mut self, problem: impl Into<String>) -> Self {
+        self.problem = problem.into();
+        self
+    }
+    pub fn context(mut self, context: Option<String>) -> Self {
+        self.context = context;
+        self
+    }
+    /// The previous plan and what went wrong with it, when the plan is being revised
+    pub fn revision(mut self, revision: Option<String>) -> Self {
+        self.revision = revision;
+        self
+    }
+    pub fn reasoning_effort(mut self, effort: impl Into<String>) -> Self {
+        self.reasoning_effort = effort.into();
+        self
+    }
+    fn prompt(&self) -> String {
+        fill(
+            PLANNER_PROMPT,
+            &[
+                ("problem", &self.problem),
+                (
+                    "context",
+                    &section(PLANNER_CONTEXT, "context", self.context.as_deref()),
+                ),
+                (
+                    "revision",
+                    &section(PLANNER_REVISION, "revision", self.revision.as_deref()),
+                ),
+            ],
+        )
+    }
+}
+
+const PLANNER_PROMPT: &str = concat!(
+    "### Instruction\n",
+    "\n",
+    "You are an expert that is knowledgeable across all domains in math. This time you are asked to plan our frontier math research before we start exploring. Please propose a proof skeleton for the following problem: a short sequence of subgoal lemmas, such that the problem follows from all of them together.\n",
+    "\n",
+    "1. Every subgoal must be a precise mathematical statement that stands alone, with all the definitions it requires. Wrap each of them inside \\begin{subgoal}\\end{subgoal}, in the order they should be proved.\n",
+    "2. Prefer subgoals that are substantially easier than the problem itself, and avoid subgoals that are likely to be false.\n",
+    "3. Finally explain how the subgoals together imply the problem inside \\begin{skeleton}\\end{skeleton}.\n",
+    "\n",
+    "### Problem\n",
+    "\n",
+    "\\begin{problem}{{problem}}\\end{problem}{{context}}{{revision}}",
+);
+const PLANNER_CONTEXT: &str = "\n\n### Context and History Explorations\n\nHere is what we have collected for this problem so far. Solved results can be used in your plan.\n\n{{context}}";
+const PLANNER_REVISION: &str = "\n\n### Previous Plan\n\nOur previous plan is shown below, and some of its subgoals turned out to be false. Please revise the plan so that it no longer relies on the refuted subgoals. Subgoals that have been proved can be kept by restating them exactly.\n\n{{revision}}";
+
+#[async_trait::async_trait]
+impl Agent for Planner {
+    async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        return self
+            .client
+            .comp(&self.prompt(), &self.model, false, &self.reasoning_effort)
             .await;
     }
 }
+
+pub struct VariantGenerator {
+    client: LMClient,
+    model: String,
+    problem: String,
+    reasoning_effort: String,
+}
+
+impl VariantGenerator {
+    pub fn new() -> Self {
+        VariantGenerator {
+            client: LMClient::new().role("variant_generator"),
+            model: String::new(),
+            problem: String::new(),
+            reasoning_effort: "medium".into(),
+        }
+    }
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+    pub fn telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.client.telemetry = Some(telemetry);
+        self
+    }
+    pub fn problem(mut self, problem: impl Into<String>) -> Self {
+        self.problem = problem.into();
+        self
+    }
+    pub fn reasoning_effort(mut self, effort: impl Into<String>) -> Self {
+        self.reasoning_effort = effort.into();
+        self
+    }
+    fn prompt(&self) -> String {
+        fill(VARIANT_GENERATOR_PROMPT, &[("problem", &self.problem)])
+    }
+}
+
+const VARIANT_GENERATOR_PROMPT: &str = concat!(
+    "### Instruction\n",
+    "\n",
+    "You are an expert that is knowledgeable across all domains in math. Mathematicians routinely solve special cases before they attack a general claim. Please propose a few simpler variants of the following research problem, whose solutions would give us insight or tools for the original problem. For example, you can:\n",
+    "\n",
+    "1. Restrict the problem to small parameters or small cases.\n",
+    "2. Add extra hypotheses that make the problem easier.\n",
+    "3. Consider the problem in a lower dimension or a simpler setting.\n",
+    "\n",
+    "Every variant must be a precise mathematical statement that stands alone, with all the definitions it requires, and should be likely to be true. Wrap each of them inside \\begin{variant}\\end{variant}, ordered from the simplest to the hardest. Propose at most five variants.\n",
+    "\n",
+    "### Problem\n",
+    "\n",
+    "\\begin{problem}{{problem}}\\end{problem}",
+);
+
+#[async_trait::async_trait]
+impl Agent for VariantGenerator {
+    async fn _process(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        return self
+            .client
+            .comp(&self.prompt(), &self.model, false, &self.reasoning_effort)
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::offline_env;
+    use std::fs;
+    use std::path::Path;
+
+    fn fixture(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/prompts")
+            .join(format!("{}.txt", name));
+        fs::read_to_string(&path).unwrap_or_else(|e| panic!("{:?}: {}", path, e))
+    }
+
+    #[test]
+    fn prompts_match_the_fixtures() {
+        // The fixtures were rendered from the same inputs before the prompts became templates
+        offline_env();
+        let problem = "Prove that $\\{x_n\\}$ converges. Keep {{context}} and {} literal.";
+        let context = "Lemma 0: $f(x) = {x}$ is continuous. {{problem}}";
+        let proof = "line 1\nline 2\nline 3\nline 4\nline 5\nline 6\nline 7\nline 8";
+        let cases: Vec<(&str, String)> = vec![
+            (
+                "explorer",
+                {
+                    let mut a = Explorer::new().persona(Some("Work by induction.".into()));
+                    a.set_problem(problem);
+                    a.set_context(context);
+                    a.set_focus(Some("Lemma 3: {{focus}}".into()));
+                    a.set_dead_ends(Some("Conjecture 5 was refuted.".into()));
+                    a.set_next_id(Some(7));
+                    a.set_feedback(Some("The dependency block was missing.".into()));
+                    a
+                }
+                .prompt(),
+            ),
+            (
+                "explorer_bare",
+                {
+                    let mut a = Explorer::new();
+                    a.set_problem(problem);
+                    a
+                }
+                .prompt(),
+            ),
+            (
+                "simple_reviewer",
+                {
+                    let mut a = SimpleReviewer::new();
+                    a.set_conjecture("CONJECTURE {{proof}}");
+                    a.set_proof(proof);
+                    a.set_context(context);
+                    a
+                }
+                .prompt(),
+            ),
+            (
+                "simple_reviewer_bare",
+                {
+                    let mut a = SimpleReviewer::new();
+                    a.set_conjecture("CONJECTURE");
+                    a.set_proof(proof);
+                    a
+                }
+                .prompt(),
+            ),
+            (
+                "definition_reviewer",
+                {
+                    let mut a = DefinitionReviewer::new();
+                    a.set_definition("A set $S$ is {nice} if ...");
+                    a.set_context(context);
+                    a
+                }
+                .prompt(),
+            ),
+            (
+                "definition_reviewer_bare",
+                {
+                    let mut a = DefinitionReviewer::new();
+                    a.set_definition("A set $S$ is {nice} if ...");
+                    a
+                }
+                .prompt(),
+            ),
+            (
+                "skeptic",
+                {
+                    let mut a = Skeptic::new();
+                    a.set_conjecture("CONJECTURE");
+                    a.set_context(context);
+                    a
+                }
+                .prompt(),
+            ),
+            (
+                "skeptic_bare",
+                {
+                    let mut a = Skeptic::new();
+                    a.set_conjecture("CONJECTURE");
+                    a
+                }
+                .prompt(),
+            ),
+            (
+                "refiner",
+                {
+                    let mut a = Refiner::new();
+                    a.set_conjecture("CONJECTURE");
+                    a.set_proof(proof);
+                    a.set_review("Step 3 is wrong.");
+                    a.set_context(context);
+                    a
+                }
+                .prompt(),
+            ),
+            (
+                "refiner_bare",
+                {
+                    let mut a = Refiner::new();
+                    a.set_conjecture("CONJECTURE");
+                    a.set_proof(proof);
+                    a.set_review("Step 3 is wrong.");
+                    a
+                }
+                .prompt(),
+            ),
+            (
+                "formatter",
+                Formatter::new()
+                    .content(proof)
+                    .kind("refuted conjectures")
+                    .prompt(),
+            ),
+            ("formatter_bare", Formatter::new().content(proof).prompt()),
+            (
+                "context_generator",
+                ContextGenerator::new().problem(problem).prompt(),
+            ),
+            (
+                "proof_summarizer",
+                ProofSummarizer::new()
+                    .conjecture("CONJECTURE")
+                    .proof(proof)
+                    .prompt(),
+            ),
+            (
+                "consolidator",
+                Consolidator::new()
+                    .problem(problem)
+                    .memory(context)
+                    .previous_digest(Some("Old digest.".into()))
+                    .prompt(),
+            ),
+            (
+                "consolidator_bare",
+                Consolidator::new()
+                    .problem(problem)
+                    .memory(context)
+                    .prompt(),
+            ),
+            (
+                "screener",
+                Screener::new()
+                    .problem(problem)
+                    .candidate("CANDIDATE")
+                    .context(Some(context.into()))
+                    .prompt(),
+            ),
+            (
+                "screener_bare",
+                Screener::new()
+                    .problem(problem)
+                    .candidate("CANDIDATE")
+                    .prompt(),
+            ),
+            (
+                "planner",
+                Planner::new()
+                    .problem(problem)
+                    .context(Some(context.into()))
+                    .revision(Some("Subgoal 2 was refuted.".into()))
+                    .prompt(),
+            ),
+            ("planner_bare", Planner::new().problem(problem).prompt()),
+            (
+                "variant_generator",
+                VariantGenerator::new().problem(problem).prompt(),
+            ),
+        ];
+        for (name, prompt) in cases {
+            assert_eq!(prompt, fixture(name), "prompt {}", name);
+        }
+    }
+
+    #[test]
+    fn progressive_prompts_match_the_fixtures() {
+        offline_env();
+        let proof = "line 1\nline 2\nline 3\nline 4\nline 5\nline 6\nline 7\nline 8";
+        let mut reviewer = ProgressiveReviewer::new();
+        reviewer.set_conjecture("PROGRESSIVE {{answer}}");
+        reviewer.set_proof(proof);
+        reviewer.set_context("Lemma 0: $f(x) = {x}$ is continuous. {{problem}}");
+        assert_eq!(reviewer.prompt(None), fixture("progressive_reviewer"));
+        let chunk_length = reviewer.chunk_length_for_iteration(proof, 1);
+        let chunks = ProgressiveReviewer::split_into_chunks(proof, chunk_length);
+        assert_eq!(chunks.len(), 2);
+        for (idx, chunk) in chunks.iter().enumerate() {
+            assert_eq!(
+                reviewer.prompt(Some((idx + 1, chunk))),
+                fixture(&format!("progressive_reviewer_chunk_{}", idx + 1))
+            );
+        }
+
+        let mut reviewer = ProgressiveReviewer::new();
+        reviewer.set_conjecture("PROGRESSIVE");
+        reviewer.set_proof(proof);
+        assert_eq!(reviewer.prompt(None), fixture("progressive_reviewer_bare"));
+    }
+
+    #[test]
+    fn fill_does_not_rescan_filled_values() {
+        let prompt = fill("{{a}} then {{b}}", &[("a", "{{b}}"), ("b", "{{a}} {x}")]);
+        assert_eq!(prompt, "{{b}} then {{a}} {x}");
+    }
+
+    #[test]
+    fn fill_keeps_latex_braces_and_unknown_placeholders() {
+        let prompt = fill(
+            "\\boxed{valid} \\begin{problem}{{problem}}\\end{problem} {{missing}} {{",
+            &[("problem", "P")],
+        );
+        assert_eq!(
+            prompt,
+            "\\boxed{valid} \\begin{problem}P\\end{problem} {{missing}} {{"
+        );
+    }
+
+    fn block(memtype: MemType, content: &str, solved: bool) -> MemoryBlock {
+        MemoryBlock::new()
+            .memtype(memtype)
+            .content(content)
+            .proof("Proof.")
+            .solved(solved)
+    }
+
+    #[test]
+    fn legacy_memory_gets_ids_in_order() {
+        let json = r#"{"memory": [
+            {"memtype": "context", "content": "C", "proof": "", "proof_summary": "",
+             "solved": true, "reviews": 0, "comment": "", "deps": []},
+            {"memtype": "lemma", "content": "A", "proof": "P", "proof_summary": "",
+             "solved": true, "reviews": 1, "comment": "", "deps": [0]},
+            {"memtype": "lemma", "content": "B", "proof": "P", "proof_summary": "",
+             "solved": true, "reviews": 1, "comment": "", "deps": [1]}
+        ]}"#;
+        let mut memory: Memory = serde_json::from_str(json).unwrap();
+        let ids: Vec<MemoryId> = memory.memory.iter().map(|m| m.get_id()).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+        assert_eq!(memory.get(2).unwrap().content, "B");
+        assert_eq!(memory.next_id(), 3);
+        assert_eq!(memory.update(block(MemType::Lemma, "D", true)), 3);
+        assert_eq!(memory.update(block(MemType::Lemma, "E", true)), 4);
+    }
+
+    #[test]
+    fn next_id_stays_above_existing_ids() {
+        // A stale `next_id` must not hand out an id that is already taken
+        let json = r#"{"next_id": 1, "memory": [
+            {"id": 0, "memtype": "context", "content": "C", "proof": "", "proof_summary": "",
+             "solved": true, "reviews": 0, "comment": "", "deps": []},
+            {"id": 7, "memtype": "lemma", "content": "A", "proof": "P", "proof_summary": "",
+             "solved": true, "reviews": 1, "comment": "", "deps": [0]},
+            {"memtype": "lemma", "content": "B", "proof": "P", "proof_summary": "",
+             "solved": true, "reviews": 1, "comment": "", "deps": []}
+        ]}"#;
+        let mut memory: Memory = serde_json::from_str(json).unwrap();
+        let ids: Vec<MemoryId> = memory.memory.iter().map(|m| m.get_id()).collect();
+        assert_eq!(ids, vec![0, 7, 2]);
+        assert_eq!(memory.next_id(), 8);
+        let new_ids: Vec<MemoryId> = (0..3)
+            .map(|_| memory.update(block(MemType::Lemma, "D", true)))
+            .collect();
+        assert_eq!(new_ids, vec![8, 9, 10]);
+
+        let json = serde_json::to_string(&memory).unwrap();
+        let reloaded: Memory = serde_json::from_str(&json).unwrap();
+        assert_eq!(reloaded.next_id(), 11);
+    }
+
+    fn deps_memory() -> Memory {
+        let mut memory = Memory::new();
+        memory.update(block(MemType::Context, "Context.", true));
+        memory.update(block(MemType::Lemma, "Solved lemma.", true));
+        memory.update(block(MemType::Lemma, "Unsolved lemma.", false));
+        memory.update(block(MemType::Counterexample, "Refuted conjecture.", true));
+        memory
+    }
+
+    #[test]
+    fn validate_deps_accepts_solved_lemmas_and_drops_the_context() {
+        let memory = deps_memory();
+        assert_eq!(memory.validate_deps(&[1, 1, 0], 0, false), Ok(vec![1]));
+        assert_eq!(memory.check_new_deps(" [0, 1] ", 0, false), Ok(vec![1]));
+        assert_eq!(memory.check_new_deps("[]", 0, false), Ok(vec![]));
+    }
+
+    #[test]
+    fn validate_deps_rejects_nonexistent_ids() {
+        let memory = deps_memory();
+        let diagnostics = memory.validate_deps(&[1, 99], 0, false).unwrap_err();
+        assert_eq!(
+            diagnostics,
+            vec!["Memory ID 99 does not exist.".to_string()]
+        );
+    }
+
+    #[test]
+    fn validate_deps_rejects_self_reference() {
+        let memory = deps_memory();
+        let own_id = memory.next_id();
+        let diagnostics = memory.validate_deps(&[own_id], 0, false).unwrap_err();
+        assert!(diagnostics[0].contains("refers to the new result itself"));
+        // Results earlier in the same response can be cited, the block itself still not
+        assert_eq!(memory.validate_deps(&[own_id], 1, false), Ok(vec![own_id]));
+        assert!(memory.validate_deps(&[own_id + 1], 1, false).is_err());
+    }
+
+    #[test]
+    fn validate_deps_rejects_unsolved_lemmas_and_counterexamples() {
+        let memory = deps_memory();
+        let diagnostics = memory.validate_deps(&[2, 3], 0, false).unwrap_err();
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics[0].contains("Memory ID 2 was refuted or is unproven"));
+        assert!(diagnostics[1].contains("Memory ID 3 is a counterexample"));
+    }
+
+    #[test]
+    fn validate_deps_requires_verified_lemmas_when_asked() {
+        let mut memory = deps_memory();
+        let diagnostics = memory.validate_deps(&[1], 0, true).unwrap_err();
+        assert!(diagnostics[0].contains("has not been verified"));
+        memory.mark_verified(1);
+        assert_eq!(memory.validate_deps(&[1], 0, true), Ok(vec![1]));
+    }
+
+    #[test]
+    fn check_new_deps_rejects_malformed_lists() {
+        let memory = deps_memory();
+        let diagnostics = memory.check_new_deps("[1, two]", 0, false).unwrap_err();
+        assert!(diagnostics[0].contains("is not a JSON array of memory IDs"));
+    }
+
+    #[test]
+    fn find_duplicate_matches_whitespace_and_latex_restatements() {
+        let mut memory = Memory::new();
+        memory.update(block(MemType::Context, "Context.", true));
+        let id = memory.update(block(
+            MemType::Lemma,
+            "For every integer $n \\geq 1$, the sum $1 + \\dots + n$ equals $n(n+1)/2$.",
+            true,
+        ));
+        let restated = block(
+            MemType::Lemma,
+            "For every integer  n \\geq 1,\nthe sum 1+\\dots+n equals n(n+1)/2.",
+            true,
+        );
+        assert_eq!(memory.find_duplicate(&restated, 0.0), Some(id));
+        // Only solved blocks of the same kind are candidates
+        let definition = block(MemType::Definition, &restated.content, true);
+        assert_eq!(memory.find_duplicate(&definition, 0.0), None);
+        memory.get_mut(id).unwrap().set_solved(false);
+        assert_eq!(memory.find_duplicate(&restated, 0.0), None);
+    }
+
+    #[test]
+    fn find_duplicate_keeps_near_misses_apart() {
+        let mut memory = Memory::new();
+        memory.update(block(
+            MemType::Lemma,
+            "For every integer $n \\geq 1$, the sum $1 + \\dots + n$ equals $n(n+1)/2$.",
+            true,
+        ));
+        let near_misses = [
+            "For every integer $n \\geq 2$, the sum $1 + \\dots + n$ equals $n(n+1)/2$.",
+            "For every integer $n \\leq 1$, the sum $1 + \\dots + n$ equals $n(n+1)/2$.",
+            "For every integer $n \\geq 1$, the sum $1 + \\dots + n$ equals $n(n-1)/2$.",
+        ];
+        for statement in near_misses {
+            let candidate = block(MemType::Lemma, statement, true);
+            assert_eq!(
+                memory.find_duplicate(&candidate, 0.0),
+                None,
+                "{}",
+                statement
+            );
+        }
+        let unrelated = block(
+            MemType::Lemma,
+            "Every finite group of prime order is cyclic.",
+            true,
+        );
+        assert_eq!(memory.find_duplicate(&unrelated, 0.5), None);
+    }
+
+    #[test]
+    fn find_duplicate_picks_the_most_similar_above_the_threshold() {
+        let mut memory = Memory::new();
+        memory.update(block(
+            MemType::Lemma,
+            "Every finite group of prime order is cyclic.",
+            true,
+        ));
+        let close = memory.update(block(
+            MemType::Lemma,
+            "Every finite group of prime order $p$ is cyclic of order $p$.",
+            true,
+        ));
+        let candidate = block(
+            MemType::Lemma,
+            "Every finite group of prime order $p$ is cyclic, of order $p$.",
+            true,
+        );
+        assert_eq!(memory.find_duplicate(&candidate, 0.6), Some(close));
+        assert_eq!(memory.find_duplicate(&candidate, 0.0), None);
+    }
+
+    fn selection_memory() -> Memory {
+        let mut memory = Memory::new();
+        memory.update(block(MemType::Context, "Background of the problem.", true));
+        for i in 1..=6 {
+            memory.update(block(
+                MemType::Lemma,
+                &format!("Lemma number {} about graphs.", i),
+                true,
+            ));
+        }
+        memory
+    }
+
+    #[test]
+    fn select_context_fills_the_token_budget() {
+        let memory = selection_memory();
+        let all = memory.select_context("graphs", &[], 0);
+        assert_eq!(all.included.len(), 7);
+        assert!(all.omitted.is_empty());
+
+        let budget = all.tokens() / 2;
+        let selection = memory.select_context("graphs", &[], budget);
+        assert!(selection.tokens() <= budget);
+        assert!(!selection.included.is_empty());
+        assert_eq!(selection.included.len() + selection.omitted.len(), 7);
+        // Every omitted block would have overflowed the budget
+        for (_, tokens) in &selection.omitted {
+            assert!(selection.tokens() + tokens > budget);
+        }
+    }
+
+    #[test]
+    fn select_context_always_keeps_the_context() {
+        let memory = selection_memory();
+        let selection = memory.select_context("graphs", &[], 1);
+        let included: Vec<MemoryId> = selection.included.iter().map(|(id, _)| *id).collect();
+        assert_eq!(included, vec![0]);
+        assert_eq!(selection.omitted.len(), 6);
+        assert!(
+            selection
+                .text
+                .unwrap()
+                .contains("Background of the problem.")
+        );
+    }
+
+    #[test]
+    fn select_context_keeps_memory_order() {
+        let mut memory = selection_memory();
+        // The focus ranks the last lemma first, it still comes after the others in the text
+        let last = memory.update(block(
+            MemType::Lemma,
+            "Planar graphs are 4-colorable.",
+            true,
+        ));
+        let budget = memory.select_context("", &[], 0).tokens() / 2;
+        let selection = memory.select_context("Planar graphs are 4-colorable.", &[last], budget);
+        let included: Vec<MemoryId> = selection.included.iter().map(|(id, _)| *id).collect();
+        assert!(included.contains(&last));
+        assert!(included.windows(2).all(|w| w[0] < w[1]), "{:?}", included);
+        let text = selection.text.unwrap();
+        let positions: Vec<usize> = included
+            .iter()
+            .map(|id| text.find(&format!("**ID: {}**", id)).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn select_statements_includes_unsolved_blocks() {
+        let mut memory = selection_memory();
+        let open = memory.update(block(
+            MemType::Lemma,
+            "An unsolved lemma about graphs.",
+            false,
+        ));
+        assert!(
+            !memory
+                .select_context("graphs", &[], 0)
+                .included
+                .iter()
+                .any(|(id, _)| *id == open)
+        );
+        let selection = memory.select_statements("graphs", 0);
+        assert!(selection.included.iter().any(|(id, _)| *id == open));
+        assert!(
+            selection
+                .text
+                .unwrap()
+                .contains(&format!("**ID: {}** (unsolved)", open))
+        );
+    }
+}
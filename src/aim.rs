@@ -1,9 +1,13 @@
+use std::sync::Arc;
+
 use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 
+use crate::events::LogObserver;
 use crate::server;
 use crate::sessions::{ResearchSession, ResearchSessionConfig, Session};
 
-use log::{error, info};
+use log::{error, info, warn};
 
 pub struct AIM {
     tokio_set: JoinSet<()>,
@@ -30,6 +34,8 @@ impl AIM {
         config: ResearchSessionConfig,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut session = ResearchSession::new(config);
+        session.add_observer(Arc::new(LogObserver));
+        watch_signals(session.cancellation_token());
         self.tokio_set.spawn(async move {
             if let Err(e) = session.run().await {
                 error!("ResearchSession failed with error: {}", e);
@@ -46,3 +52,37 @@ impl AIM {
         Ok(())
     }
 }
+
+/// Interrupt the session on the first SIGINT or SIGTERM, and exit on the second one
+fn watch_signals(token: CancellationToken) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = shutdown_signal().await {
+                error!("Failed to listen for shutdown signals: {}", e);
+                return;
+            }
+            if token.is_cancelled() {
+                warn!("Received a second shutdown signal, exiting immediately.");
+                std::process::exit(130);
+            }
+            warn!(
+                "Received a shutdown signal, saving the session. Send it again to exit immediately."
+            );
+            token.cancel();
+        }
+    });
+}
+
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
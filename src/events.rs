@@ -0,0 +1,175 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::agents::{MemType, MemoryId};
+
+use chrono::Utc;
+use log::{debug, error};
+use serde::Serialize;
+
+/// Progress of a research session, published to every observer of the session
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SessionEvent {
+    StepStarted {
+        step: u32,
+    },
+    ExplorationReceived {
+        conjectures: usize,
+        final_proof: bool,
+    },
+    LemmaAdded {
+        memid: MemoryId,
+        statement: String,
+    },
+    /// The current state of a memory block right after it changed
+    MemoryUpdated {
+        memid: MemoryId,
+        memtype: MemType,
+        solved: bool,
+        verified: bool,
+        content: String,
+        proof: String,
+        comment: String,
+    },
+    /// `review` holds the flaw found by the reviewers, `None` when the proof passed
+    ReviewVerdict {
+        statement: String,
+        review: Option<String>,
+    },
+    Refinement {
+        statement: String,
+        refuted: bool,
+    },
+    TheoremVerified {
+        memid: MemoryId,
+    },
+    Finished {
+        status: String,
+    },
+    /// A remote session stored its project in the database
+    ProjectCreated {
+        project_id: u64,
+    },
+    /// An event of the disproof track, whose memory IDs refer to the memory of that track
+    DisproofTrack {
+        inner: Box<SessionEvent>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Receives the events of a research session. Observers are called on the session task and
+/// should hand expensive work over to their own tasks.
+pub trait SessionObserver: Send + Sync {
+    fn on_event(&self, event: &SessionEvent);
+}
+
+/// The observers subscribed to one session
+#[derive(Clone, Default)]
+pub struct Observers(Vec<Arc<dyn SessionObserver>>);
+
+impl Observers {
+    pub fn add(&mut self, observer: Arc<dyn SessionObserver>) {
+        self.0.push(observer);
+    }
+    pub fn emit(&self, event: SessionEvent) {
+        for observer in &self.0 {
+            observer.on_event(&event);
+        }
+    }
+    /// The same observers, receiving the events of the disproof track
+    pub fn disproof_track(&self) -> Observers {
+        Observers(
+            self.0
+                .iter()
+                .map(|observer| {
+                    Arc::new(DisproofObserver(observer.clone())) as Arc<dyn SessionObserver>
+                })
+                .collect(),
+        )
+    }
+}
+
+struct DisproofObserver(Arc<dyn SessionObserver>);
+
+impl SessionObserver for DisproofObserver {
+    fn on_event(&self, event: &SessionEvent) {
+        self.0.on_event(&SessionEvent::DisproofTrack {
+            inner: Box::new(event.clone()),
+        });
+    }
+}
+
+/// Writes every event to the log
+pub struct LogObserver;
+
+impl SessionObserver for LogObserver {
+    fn on_event(&self, event: &SessionEvent) {
+        match event {
+            SessionEvent::Error { message } => error!("Session error: {}", message),
+            _ => debug!("Session event: {:?}", event),
+        }
+    }
+}
+
+/// Append-only log of a session in JSON lines, recording its agent calls and events
+pub struct JsonlLog {
+    path: PathBuf,
+    file: Mutex<fs::File>,
+}
+
+impl JsonlLog {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(JsonlLog {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one record of the given kind, stamped with the current time
+    pub fn write(&self, kind: &str, record: &impl Serialize) {
+        let mut line = serde_json::json!({
+            "time": Utc::now().to_rfc3339(),
+            "kind": kind,
+        });
+        match serde_json::to_value(record) {
+            Ok(serde_json::Value::Object(fields)) => {
+                if let Some(line) = line.as_object_mut() {
+                    line.extend(fields);
+                }
+            }
+            Ok(value) => line["record"] = value,
+            Err(e) => {
+                error!("Failed to serialize a record of the session log: {}", e);
+                return;
+            }
+        }
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+        if let Err(e) = writeln!(file, "{}", line) {
+            error!("Failed to write the session log {:?}: {}", self.path, e);
+        }
+    }
+}
+
+impl SessionObserver for JsonlLog {
+    fn on_event(&self, event: &SessionEvent) {
+        self.write("event", event);
+    }
+}
@@ -1,12 +1,15 @@
 mod agents;
 mod aim;
+mod events;
+mod manifest;
 mod server;
 mod sessions;
+mod strategies;
+mod tracer;
 mod utils;
 use crate::aim::AIM;
 use crate::sessions::ResearchSessionConfig;
 
-use env_logger;
 use log::error;
 
 use clap::Parser;
@@ -51,6 +54,55 @@ struct Cli {
     #[arg(long = "reasoning_effort", default_value = "high")]
     reasoning_effort: String,
 
+    /// Text similarity above which a new lemma is merged into an existing one, restated lemmas
+    /// are always merged (0 to only merge restatements)
+    #[arg(long = "dedup_threshold", default_value_t = 0.0)]
+    dedup_threshold: f64,
+    /// Embedding model used to detect restated lemmas, disabled when not given
+    #[arg(long = "embedding_model")]
+    embedding_model: Option<String>,
+
+    /// Estimated token budget of the memory context given to agents (0 for unlimited)
+    #[arg(long = "context_budget", default_value_t = 96000)]
+    context_budget: usize,
+
+    /// Steps between memory consolidations into a research digest (0 to disable)
+    #[arg(long = "consolidation_interval", default_value_t = 8)]
+    consolidation_interval: u32,
+    /// Number of lemmas after which the explorer reads the digest in place of the lemma list
+    #[arg(long = "digest_threshold", default_value_t = 24)]
+    digest_threshold: usize,
+
+    /// Explorations sampled concurrently in each graph step (1 disables beam mode)
+    #[arg(long = "beam_width", default_value_t = 1)]
+    beam_width: u8,
+    /// Number of screened explorations inserted into memory in beam mode
+    #[arg(long = "beam_keep", default_value_t = 1)]
+    beam_keep: u8,
+
+    /// Refinements sampled and reviewed in parallel for each flawed proof (1 refines sequentially)
+    #[arg(long = "refinement_candidates", default_value_t = 1)]
+    refinement_candidates: u8,
+
+    /// Total tokens the session may spend (0 for unlimited)
+    #[arg(long = "token_budget", default_value_t = 0)]
+    token_budget: u64,
+    /// Estimated cost in USD the session may spend (0 for unlimited)
+    #[arg(long = "cost_budget", default_value_t = 0.0)]
+    cost_budget: f64,
+    /// Wall-clock minutes the session may run (0 for unlimited)
+    #[arg(long = "time_budget", default_value_t = 0)]
+    time_budget: u64,
+    /// API calls the session may make, not counting retries (0 for unlimited)
+    #[arg(long = "call_budget", default_value_t = 0)]
+    call_budget: u64,
+    /// Price in USD per million prompt tokens, used to estimate the cost
+    #[arg(long = "input_price", default_value_t = 0.0)]
+    input_price: f64,
+    /// Price in USD per million completion tokens, used to estimate the cost
+    #[arg(long = "output_price", default_value_t = 0.0)]
+    output_price: f64,
+
     /// Resume from previous memory in a session directory
     #[arg(long = "resume", action = clap::ArgAction::SetTrue, default_value_t = false)]
     resume: bool,
@@ -67,27 +119,41 @@ struct Cli {
     #[arg(long = "no_tgm", action = clap::ArgAction::SetFalse, default_value_t = true)]
     theorem_graph_mode: bool,
 
+    /// Exploration strategy ("linear", "graph", "best_first", "planned" or "variants"), chosen by theorem graph mode if not given
+    #[arg(long = "strategy")]
+    strategy: Option<String>,
+
+    /// Disable the skeptic that attacks new conjectures before their review
+    #[arg(long = "no_skeptic", action = clap::ArgAction::SetFalse, default_value_t = true)]
+    skeptic: bool,
+
+    /// Also explore the negation of the problem, ending when either direction is proved
+    #[arg(long = "disproof", action = clap::ArgAction::SetTrue, default_value_t = false)]
+    disproof: bool,
+
     /// Running AIM as a server backend
     #[arg(long = "server", action = clap::ArgAction::SetTrue, default_value_t = false)]
     server: bool,
     /// Port to bind in server mode
     #[arg(long = "port", default_value_t = 4000)]
     port: u16,
+
+    /// Log output: pretty, json, or otlp (pretty output plus spans exported to a collector)
+    #[arg(long = "log_format", default_value = "pretty", value_parser = ["pretty", "json", "otlp"])]
+    log_format: String,
+    /// OTLP/HTTP traces endpoint used by --log_format otlp
+    #[arg(
+        long = "otlp_endpoint",
+        default_value = "http://localhost:4318/v1/traces"
+    )]
+    otlp_endpoint: String,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let cli = Cli::parse();
-    use log::LevelFilter;
-    // Initialize logger: default to 'info', but in --server mode silence agents & sessions to 'error'
-    let env = env_logger::Env::default().default_filter_or("info");
-    let mut builder = env_logger::Builder::from_env(env);
-    builder.target(env_logger::Target::Stdout);
-    if cli.server {
-        builder.filter_module("aim::agents", LevelFilter::Error);
-        builder.filter_module("aim::sessions", LevelFilter::Error);
-    }
-    builder.init();
+    // Initialize logging & tracing: default to 'info', but in --server mode silence agents & sessions to 'error'
+    let _tracing = tracer::init(&cli.log_format, &cli.otlp_endpoint, cli.server)?;
 
     let mut aim = AIM::new();
     if let Some(p) = cli.problem.as_deref() {
@@ -105,7 +171,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             .reformat(cli.reformat)
             .streaming(cli.streaming)
             .theorem_graph_mode(cli.theorem_graph_mode)
-            .reasoning_effort(cli.reasoning_effort);
+            .strategy(cli.strategy.unwrap_or_default())
+            .disproof(cli.disproof)
+            .skeptic(cli.skeptic)
+            .reasoning_effort(cli.reasoning_effort)
+            .dedup_threshold(cli.dedup_threshold)
+            .context_budget(cli.context_budget)
+            .consolidation_interval(cli.consolidation_interval)
+            .digest_threshold(cli.digest_threshold)
+            .beam_width(cli.beam_width)
+            .beam_keep(cli.beam_keep)
+            .refinement_candidates(cli.refinement_candidates)
+            .token_budget(cli.token_budget)
+            .cost_budget(cli.cost_budget)
+            .time_budget(cli.time_budget)
+            .call_budget(cli.call_budget)
+            .prices(cli.input_price, cli.output_price)
+            .embedding_model(cli.embedding_model.unwrap_or_default());
         let _ = aim.run_session(config).await;
     } else if cli.server {
         // Bind to all interfaces on the given port
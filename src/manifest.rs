@@ -0,0 +1,39 @@
+use std::collections::BTreeMap;
+
+use crate::agents::{PROMPT_TEMPLATES, RoleSettings, Usage};
+use crate::sessions::ResearchSessionConfig;
+use crate::utils::content_hash;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+pub const AIM_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Commit AIM was built from, suffixed with "-dirty" when built with uncommitted changes
+pub const GIT_COMMIT: &str = env!("AIM_GIT_COMMIT");
+
+/// What a run was made of and what it produced, so that two runs can be compared and reproduced
+#[derive(Serialize)]
+pub struct RunManifest<'a> {
+    pub aim_version: &'static str,
+    pub git_commit: &'static str,
+    // Times and status are those of this run
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub status: &'a str,
+    pub config: &'a ResearchSessionConfig,
+    pub prompt_hashes: BTreeMap<String, String>,
+    pub roles: BTreeMap<&'static str, RoleSettings>,
+    // Nodes, usage and cost cover the whole session, including the runs it resumed
+    pub nodes: BTreeMap<String, usize>,
+    pub verified_nodes: usize,
+    pub usage: Usage,
+    pub cost: f64,
+}
+
+/// Hash of the prompt templates of every agent role
+pub fn prompt_hashes() -> BTreeMap<String, String> {
+    PROMPT_TEMPLATES
+        .iter()
+        .map(|(role, templates)| (role.to_string(), content_hash(templates)))
+        .collect()
+}
@@ -1,4 +1,5 @@
-use crate::agents::default_datetime;
+use crate::agents::{MemoryId, default_datetime};
+use crate::events::{SessionEvent, SessionObserver};
 use crate::server::entity::user::{
     ActiveModel as UserActiveModel, Column as UserColumn, Entity as User,
 };
@@ -17,9 +18,12 @@ use sea_orm::{
     EntityTrait, QueryFilter, Set, Statement,
 };
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use tokio::signal::ctrl_c;
 #[cfg(unix)]
 use tokio::signal::unix::{SignalKind, signal as unix_signal};
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tracing_actix_web::TracingLogger;
 /// Check if the given email is listed in the AIM_ADMIN_EMAIL env var (comma-separated)
 fn is_admin_email(user_email: &str) -> bool {
     match std::env::var("AIM_ADMIN_EMAIL") {
@@ -44,6 +48,15 @@ async fn serve_frontend_index(req: HttpRequest) -> actix_web::Result<NamedFile>
     Ok(NamedFile::open("./frontend/out/index.html")?)
 }
 
+/// Directory of the session logs of projects, from AIM_PROJECT_LOG_DIR or `logs/projects`
+fn project_log_root() -> String {
+    let root = std::env::var("AIM_PROJECT_LOG_DIR").unwrap_or_else(|_| "logs/projects".into());
+    // Stored with the project, so a relative directory is resolved against the server directory
+    std::path::absolute(&root)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or(root)
+}
+
 /// JWT claims
 #[derive(Debug, Serialize, Deserialize)]
 struct Claims {
@@ -118,7 +131,10 @@ pub async fn run(bind_addr: &str) -> std::io::Result<()> {
             lemmas_count INTEGER NOT NULL,
             status TEXT NOT NULL DEFAULT 'running',
             comment TEXT NOT NULL DEFAULT '',
-            error TEXT NOT NULL DEFAULT ''
+            error TEXT NOT NULL DEFAULT '',
+            report TEXT NOT NULL DEFAULT '',
+            progress TEXT NOT NULL DEFAULT '',
+            event_log TEXT NOT NULL DEFAULT ''
         );
     "#;
     db.execute(Statement::from_string(
@@ -143,6 +159,36 @@ pub async fn run(bind_addr: &str) -> std::io::Result<()> {
             ));
         }
     }
+    // Backfill the report column, the progress column holding the latest session event of a
+    // project, and the path of the session log of a project
+    for (column, ensure_column) in [
+        (
+            "report",
+            "ALTER TABLE projects ADD COLUMN report TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "progress",
+            "ALTER TABLE projects ADD COLUMN progress TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            "event_log",
+            "ALTER TABLE projects ADD COLUMN event_log TEXT NOT NULL DEFAULT ''",
+        ),
+    ] {
+        if let Err(e) = db
+            .execute(Statement::from_string(
+                DbBackend::Sqlite,
+                ensure_column.to_owned(),
+            ))
+            .await
+            && !e.to_string().contains("duplicate column name")
+        {
+            return Err(std::io::Error::other(format!(
+                "Failed to ensure {} column: {}",
+                column, e
+            )));
+        }
+    }
     if let Err(e) = cancel_running_projects(&db, "Server restarted before completion").await {
         error!("Failed to mark unfinished projects as canceled: {}", e);
     }
@@ -158,6 +204,7 @@ pub async fn run(bind_addr: &str) -> std::io::Result<()> {
         App::new()
             .app_data(web::Data::new(db_pool.clone()))
             .wrap(cors)
+            .wrap(TracingLogger::default())
             // API routes under /api
             .service(
                 web::scope("/api")
@@ -602,6 +649,23 @@ struct NewProjectRequest {
     iterations: u8,
     reformat: bool,
     theorem_graph: bool,
+    /// Also search for a disproof of the problem
+    #[serde(default)]
+    disproof: bool,
+    /// Budgets that end the session early, 0 for unlimited
+    #[serde(default)]
+    token_budget: u64,
+    #[serde(default)]
+    cost_budget: f64,
+    #[serde(default)]
+    time_budget: u64,
+    #[serde(default)]
+    call_budget: u64,
+    /// Prices in USD per million prompt and completion tokens, used to estimate the cost
+    #[serde(default)]
+    input_price: f64,
+    #[serde(default)]
+    output_price: f64,
     /// Reasoning effort for reasoning-capable models ("minimal" | "low" | "medium" | "high")
     #[serde(default = "default_reasoning_effort")]
     reasoning_effort: String,
@@ -761,6 +825,13 @@ async fn handle_new_project(
         .reformat(req.reformat)
         .streaming(false)
         .theorem_graph_mode(req.theorem_graph)
+        .disproof(req.disproof)
+        .project_logs(project_log_root())
+        .token_budget(req.token_budget)
+        .cost_budget(req.cost_budget)
+        .time_budget(req.time_budget)
+        .call_budget(req.call_budget)
+        .prices(req.input_price, req.output_price)
         .reasoning_effort(req.reasoning_effort);
     config.set_problem(req.problem);
     if let Some(c) = req.context {
@@ -770,6 +841,7 @@ async fn handle_new_project(
     let db_conn = db.get_ref().clone();
     tokio::spawn(async move {
         let mut session = ResearchSession::new(config);
+        session.add_observer(ProjectObserver::spawn(db_conn.clone()));
         if let Err(e) = session.remote_run(&db_conn, user_id).await {
             error!("remote_run failed: {}", e);
         }
@@ -909,6 +981,9 @@ async fn handle_list_projects(
 /// Return full project details including memory blocks
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MemoryBlockResponse {
+    /// Stable memory id, missing in memory blobs stored before ids were introduced
+    #[serde(default)]
+    pub id: Option<MemoryId>,
     pub memtype: String,
     pub content: String,
     pub proof: String,
@@ -922,8 +997,58 @@ pub struct MemoryBlockResponse {
     solved: bool,
     reviews: u8,
     comment: String,
-    deps: Vec<usize>,
+    deps: Vec<MemoryId>,
+}
+
+/// Records the latest event of a remote session in its project row, so that clients follow the
+/// progress within a step instead of only the memory written after each step
+struct ProjectObserver {
+    events: UnboundedSender<SessionEvent>,
+}
+
+impl ProjectObserver {
+    fn spawn(db: DatabaseConnection) -> Arc<Self> {
+        // Events are written by a task of their own, which ends when the session is dropped
+        let (events, mut receiver) = unbounded_channel::<SessionEvent>();
+        tokio::spawn(async move {
+            let mut project_id = None;
+            while let Some(event) = receiver.recv().await {
+                if let SessionEvent::ProjectCreated { project_id: id } = event {
+                    project_id = Some(id);
+                    continue;
+                }
+                let Some(id) = project_id else {
+                    continue;
+                };
+                let Ok(progress) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                let now = Utc::now().to_rfc3339();
+                let sql = format!(
+                    "UPDATE projects SET progress='{}', last_active='{}' WHERE id={}",
+                    progress.replace("'", "''"),
+                    now,
+                    id
+                );
+                if let Err(e) = db
+                    .execute(Statement::from_string(DbBackend::Sqlite, sql))
+                    .await
+                {
+                    error!("Failed to record the progress of project {}: {}", id, e);
+                }
+            }
+        });
+        Arc::new(ProjectObserver { events })
+    }
 }
+
+impl SessionObserver for ProjectObserver {
+    fn on_event(&self, event: &SessionEvent) {
+        let _ = self.events.send(event.clone());
+    }
+}
+
+#[tracing::instrument(skip_all, fields(project_id = path.0))]
 async fn handle_get_project(
     db: web::Data<DatabaseConnection>,
     req: HttpRequest,
@@ -967,12 +1092,12 @@ async fn handle_get_project(
     // Fetch project detail including comment
     let sql = if is_admin {
         format!(
-            "SELECT p.id, p.title, p.problem, p.context, p.memory, p.config, p.created_at, p.last_active, p.lemmas_count, p.status, p.comment, p.error, u.full_name AS creator FROM projects p JOIN users u ON p.user_id = u.id WHERE p.id={} LIMIT 1",
+            "SELECT p.id, p.title, p.problem, p.context, p.memory, p.config, p.created_at, p.last_active, p.lemmas_count, p.status, p.comment, p.error, p.report, p.progress, p.event_log, u.full_name AS creator FROM projects p JOIN users u ON p.user_id = u.id WHERE p.id={} LIMIT 1",
             project_id
         )
     } else {
         format!(
-            "SELECT p.id, p.title, p.problem, p.context, p.memory, p.config, p.created_at, p.last_active, p.lemmas_count, p.status, p.comment, p.error, u.full_name AS creator FROM projects p JOIN users u ON p.user_id = u.id WHERE p.id={} AND p.user_id={} LIMIT 1",
+            "SELECT p.id, p.title, p.problem, p.context, p.memory, p.config, p.created_at, p.last_active, p.lemmas_count, p.status, p.comment, p.error, p.report, p.progress, p.event_log, u.full_name AS creator FROM projects p JOIN users u ON p.user_id = u.id WHERE p.id={} AND p.user_id={} LIMIT 1",
             project_id, user_id
         )
     };
@@ -989,8 +1114,12 @@ async fn handle_get_project(
             let context: Option<String> = row.try_get("", "context").ok().flatten();
             let mem_json: String = row.try_get("", "memory").unwrap_or_default();
             let config_json: String = row.try_get("", "config").unwrap_or_default();
-            let memory: Vec<MemoryBlockResponse> =
+            let mut memory: Vec<MemoryBlockResponse> =
                 serde_json::from_str(&mem_json).unwrap_or_default();
+            // Legacy memory blocks are identified by their positions
+            for (pos, memblock) in memory.iter_mut().enumerate() {
+                memblock.id.get_or_insert(pos);
+            }
             let created_at: String = row.try_get("", "created_at").unwrap_or_default();
             let last_active: String = row.try_get("", "last_active").unwrap_or_default();
             let lemmas_count: i32 = row.try_get("", "lemmas_count").unwrap_or_default();
@@ -999,6 +1128,9 @@ async fn handle_get_project(
                 .unwrap_or_else(|_| "running".to_string());
             let comment: String = row.try_get("", "comment").unwrap_or_default();
             let error_msg: String = row.try_get("", "error").unwrap_or_default();
+            let report: String = row.try_get("", "report").unwrap_or_default();
+            let progress: String = row.try_get("", "progress").unwrap_or_default();
+            let event_log: String = row.try_get("", "event_log").unwrap_or_default();
             let creator: String = row.try_get("", "creator").unwrap_or_default();
             #[derive(Serialize)]
             struct ProjectDetail {
@@ -1013,6 +1145,9 @@ async fn handle_get_project(
                 status: String,
                 comment: String,
                 error: String,
+                report: String,
+                progress: String,
+                event_log: String,
                 config: String,
                 creator: String,
             }
@@ -1028,6 +1163,9 @@ async fn handle_get_project(
                 status,
                 comment,
                 error: error_msg,
+                report,
+                progress,
+                event_log,
                 config: config_json,
                 creator,
             };
@@ -1054,6 +1192,7 @@ struct CommentRequest {
 
 /// POST /api/project/{id}/comment
 /// Update or set the notes/comment for a project
+#[tracing::instrument(skip_all, fields(project_id = path.0))]
 async fn handle_update_comment(
     db: web::Data<DatabaseConnection>,
     req: HttpRequest,
@@ -1122,6 +1261,7 @@ async fn handle_update_comment(
 }
 
 /// DELETE /api/project/{id}: delete a project owned by the authenticated user
+#[tracing::instrument(skip_all, fields(project_id = path.0))]
 async fn handle_delete_project(
     db: web::Data<DatabaseConnection>,
     req: HttpRequest,
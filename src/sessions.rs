@@ -1,16 +1,27 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use crate::agents::{
-    Agent, ContextGenerator, Explorer, Formatter, Memory, MemoryBlock, ProgressiveReviewResult,
-    ProgressiveReviewer, ProofSummarizer, Refiner, SimpleReviewer,
+    Agent, Budget, Consolidator, ContextGenerator, ContextSelection, DefinitionReviewer, Embedder,
+    Explorer, Formatter, MemType, Memory, MemoryBlock, MemoryId, Planner, ProgressiveReviewResult,
+    ProgressiveReviewer, ProofSummarizer, Refiner, Screener, SimpleReviewer, Skeptic, Telemetry,
+    Usage, VariantGenerator,
+};
+use crate::events::{JsonlLog, Observers, SessionEvent, SessionObserver};
+use crate::manifest::{self, RunManifest};
+use crate::strategies::{ExplorationStrategy, build_strategy};
+use crate::utils::{
+    content_hash, cosine_similarity, extract_all_component, extract_component, find_box,
+    normalize_text, write_atomic,
 };
-use crate::utils::{extract_all_component, extract_component, find_box};
 use indicatif::{ProgressBar, ProgressStyle};
 use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, info_span};
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use log::{debug, error, info, warn};
 use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
 use serde::{Deserialize, Serialize};
@@ -27,10 +38,439 @@ pub trait Session: Send {
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
 }
 
+// Reviews a node accumulates before backtrace reviews skip it, as long as its content is
+// unchanged since it last passed. A verified node below the threshold is reviewed again.
 const MAX_REVIEWS_PER_NODE: u8 = 24;
 const MAX_PROGRESSIVE_REVIEWS_PER_NODE: u8 = 63;
+const MAX_DEPENDENCY_RETRIES: u8 = 2;
+const EMBEDDING_DUPLICATE_THRESHOLD: f64 = 0.95;
+// Skeptic samples that must all find a counterexample before a conjecture is dropped
+const SKEPTIC_SAMPLES: usize = 2;
+const DISPROOF_PREFIX: &str = "Disprove the following statement, that is, prove that it is false, for example by constructing an explicit counterexample:\n\n";
+// Research styles cycled through by explorations sampled in beam mode
+const EXPLORER_PERSONAS: [&str; 4] = [
+    "",
+    "Prefer small, concrete lemmas that are easy to verify and clearly useful for later steps.",
+    "Prefer bold conjectures that make substantial progress towards the original problem.",
+    "Look for an approach that differs from the directions already explored in the context.",
+];
+
+fn dependency_feedback(diagnostics: &[String]) -> String {
+    let issues = diagnostics
+        .iter()
+        .map(|d| format!("- {}", d))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "The dependency list in your response is invalid:\n\n{}\n\nOnly verified lemmas listed in the context can be cited, using their exact memory IDs.",
+        issues
+    )
+}
+
+/// One parsed response of the explorer in theorem graph mode
+struct GraphExploration {
+    conj: String,
+    proof: String,
+    final_proof: String,
+    deps: Vec<MemoryId>,
+}
+
+impl GraphExploration {
+    fn describe(&self) -> String {
+        if self.final_proof.is_empty() {
+            format!(
+                "\\begin{{conjecture}}{}\\end{{conjecture}}\n\\begin{{proof}}{}\\end{{proof}}",
+                self.conj, self.proof
+            )
+        } else {
+            format!(
+                "A proof of the original problem:\n\n\\begin{{proof}}{}\\end{{proof}}",
+                self.final_proof
+            )
+        }
+    }
+}
+
+enum ExplorationError {
+    Incomplete,
+    InvalidDeps(Vec<String>),
+}
+
+fn parse_graph_exploration(
+    raw: &str,
+    memory: &Memory,
+) -> Result<GraphExploration, ExplorationError> {
+    let conj = extract_component(raw, "conjecture").unwrap_or_default();
+    let proof = extract_component(raw, "proof").unwrap_or_default();
+    let final_proof = extract_component(raw, "final_proof").unwrap_or_default();
+    let deps = extract_component(raw, "dependency").unwrap_or_default();
+    if (conj.is_empty() || proof.is_empty() || deps.is_empty())
+        && (final_proof.is_empty() || deps.is_empty())
+    {
+        error!(
+            "Incomplete response format: conjecture {}; proof {}; dependency {}; final_proof: {};",
+            !conj.is_empty(),
+            !proof.is_empty(),
+            !deps.is_empty(),
+            !final_proof.is_empty()
+        );
+        return Err(ExplorationError::Incomplete);
+    }
+    // Lemmas of the graph are only reviewed along the proof path of a final proof, so unreviewed
+    // ones may be cited while refuted ones may not
+    match memory.check_new_deps(&deps, 0, false) {
+        Ok(deps) => Ok(GraphExploration {
+            conj,
+            proof,
+            final_proof,
+            deps,
+        }),
+        Err(diagnostics) => Err(ExplorationError::InvalidDeps(diagnostics)),
+    }
+}
+
+fn resolve_new_ids(
+    deps: &[MemoryId],
+    first_new_id: MemoryId,
+    inserted: &[Option<MemoryId>],
+) -> Option<Vec<MemoryId>> {
+    // Replace the ids announced for the conjectures of a response with the ids they ended up as,
+    // `None` when a cited conjecture was dropped
+    deps.iter()
+        .map(|dep| match dep.checked_sub(first_new_id) {
+            Some(i) => inserted.get(i).copied().flatten(),
+            None => Some(*dep),
+        })
+        .collect()
+}
+
+/// How the verification of a single conjecture ended
+enum Verdict {
+    Verified(u8),
+    Failed(String),
+    FormatError,
+}
+
+/// Reviewers and refiner used to verify one statement, owned by the statement so that several
+/// statements or refinement candidates can be checked concurrently
+#[derive(Clone)]
+struct Verifier {
+    reviewer: String,
+    reviews: u8,
+    candidates: u8,
+    simple_reviewer: SimpleReviewer,
+    progressive_reviewer: ProgressiveReviewer,
+    refiner: Refiner,
+    observers: Observers,
+}
+
+/// One rewrite of the refiner, with the review of the rewritten proof if it was already checked
+struct Refinement {
+    raw: String,
+    review: Option<(Option<String>, u8)>,
+}
+
+fn refined_statement(raw: &str) -> Option<String> {
+    // A refiner judging the statement false proves its corrected version instead
+    if find_box(raw)? == "false" {
+        extract_component(raw, "conjecture")
+    } else {
+        None
+    }
+}
+
+impl Verifier {
+    fn new(
+        config: &ResearchSessionConfig,
+        telemetry: &Arc<Telemetry>,
+        observers: &Observers,
+    ) -> Self {
+        Verifier {
+            reviewer: config.reviewer.clone(),
+            reviews: config.reviews,
+            candidates: config.refinement_candidates,
+            simple_reviewer: SimpleReviewer::new()
+                .telemetry(telemetry.clone())
+                .model(&config.eval_model)
+                .reviews(config.reviews)
+                .streaming(false)
+                .reasoning_effort(config.reasoning_effort.clone()),
+            progressive_reviewer: ProgressiveReviewer::new()
+                .telemetry(telemetry.clone())
+                .model(&config.eval_model)
+                .max_iters(config.max_review_iters as usize)
+                .reasoning_effort(config.reasoning_effort.clone()),
+            refiner: Refiner::new()
+                .telemetry(telemetry.clone())
+                .model(&config.proof_model)
+                .streaming(false)
+                .reasoning_effort(config.reasoning_effort.clone()),
+            observers: observers.clone(),
+        }
+    }
+
+    fn set_context(&mut self, context: &str) {
+        self.simple_reviewer.set_context(context);
+        self.progressive_reviewer.set_context(context);
+        self.refiner.set_context(context);
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
+    async fn review(
+        &self,
+        conj: &str,
+        proof: &str,
+    ) -> Result<(Option<String>, u8), Box<dyn std::error::Error + Send + Sync>> {
+        // A reviewer that could not be called fails the review, the proof is not accepted
+        let (review, calls) = if self.reviewer == "progressive" {
+            let mut reviewer = self.progressive_reviewer.clone();
+            reviewer.set_conjecture(conj);
+            reviewer.set_proof(proof);
+            let result = reviewer.verify().await?;
+            (result.review, result.api_calls)
+        } else {
+            let mut reviewer = self.simple_reviewer.clone();
+            reviewer.set_conjecture(conj);
+            reviewer.set_proof(proof);
+            (Arc::new(reviewer).pverify().await?, self.reviews)
+        };
+        self.observers.emit(SessionEvent::ReviewVerdict {
+            statement: conj.to_string(),
+            review: review.clone(),
+        });
+        Ok((review, calls))
+    }
+
+    fn emit_refinement(&self, conj: &str, raw: &str) {
+        self.observers.emit(SessionEvent::Refinement {
+            statement: conj.to_string(),
+            refuted: refined_statement(raw).is_some(),
+        });
+    }
+
+    async fn refine(
+        &self,
+        conj: &str,
+        proof: &str,
+        review: &str,
+    ) -> Result<Refinement, Box<dyn std::error::Error + Send + Sync>> {
+        let mut refiner = self.refiner.clone();
+        refiner.set_conjecture(conj);
+        refiner.set_proof(proof);
+        refiner.set_review(review);
+        if self.candidates <= 1 {
+            let raw = refiner._process().await?;
+            self.emit_refinement(conj, &raw);
+            return Ok(Refinement { raw, review: None });
+        }
+
+        // Sample several rewrites concurrently and keep the first one that passes its review
+        info!("Sampling {} refinement candidates", self.candidates);
+        let mut tasks: JoinSet<(usize, Option<Refinement>)> = JoinSet::new();
+        for i in 0..self.candidates as usize {
+            let refiner = refiner.clone().streaming(false);
+            let mut verifier = self.clone();
+            verifier.simple_reviewer = verifier.simple_reviewer.streaming(false);
+            let (conj, proof) = (conj.to_string(), proof.to_string());
+            let span = info_span!("refinement_candidate", candidate = i);
+            tasks.spawn(
+                async move {
+                    let raw = match refiner._process().await {
+                        Ok(raw) => raw,
+                        Err(e) => {
+                            error!("Refinement candidate {} failed: {}", i, e);
+                            return (i, None);
+                        }
+                    };
+                    if find_box(&raw).is_none() {
+                        warn!("Dropped refinement candidate {} with a format error", i);
+                        return (i, None);
+                    }
+                    let statement = refined_statement(&raw).unwrap_or(conj);
+                    let proof = extract_component(&raw, "proof").unwrap_or(proof);
+                    let review = match verifier.review(&statement, &proof).await {
+                        Ok(review) => review,
+                        Err(e) => {
+                            error!("Review of refinement candidate {} failed: {}", i, e);
+                            return (i, None);
+                        }
+                    };
+                    (
+                        i,
+                        Some(Refinement {
+                            raw,
+                            review: Some(review),
+                        }),
+                    )
+                }
+                .instrument(span),
+            );
+        }
+        let mut candidates: Vec<(usize, Refinement)> = Vec::new();
+        while let Some(res) = tasks.join_next().await {
+            if let Ok((i, Some(refinement))) = res {
+                candidates.push((i, refinement));
+            }
+        }
+        candidates.sort_by_key(|(i, _)| *i);
+        let passed = |r: &Refinement| r.review.as_ref().is_some_and(|(rev, _)| rev.is_none());
+        info!(
+            "{} of {} refinement candidates passed their review",
+            candidates.iter().filter(|(_, r)| passed(r)).count(),
+            candidates.len()
+        );
+        let used_reviews = candidates
+            .iter()
+            .filter_map(|(_, r)| r.review.as_ref())
+            .fold(0u8, |acc, (_, calls)| acc.saturating_add(*calls));
+        let chosen = candidates
+            .iter()
+            .position(|(_, r)| passed(r))
+            .unwrap_or_default();
+        if candidates.is_empty() {
+            return Ok(Refinement {
+                raw: String::new(),
+                review: None,
+            });
+        }
+        let (_, mut refinement) = candidates.swap_remove(chosen);
+        if let Some((_, calls)) = refinement.review.as_mut() {
+            *calls = used_reviews;
+        }
+        self.emit_refinement(conj, &refinement.raw);
+        Ok(refinement)
+    }
+}
+
+/// A conjecture of one exploration verified with its own reviewers and refiner, so that all
+/// conjectures of the exploration can be verified concurrently
+struct ConjectureVerification {
+    conj: String,
+    proof: String,
+    deps: Vec<MemoryId>,
+    iterations: u8,
+    verifier: Verifier,
+    /// Refuted versions of the conjecture met during refinement
+    counterexamples: Vec<MemoryBlock>,
+}
+
+impl ConjectureVerification {
+    async fn run(mut self) -> Result<(Self, Verdict), Box<dyn std::error::Error + Send + Sync>> {
+        let mut used_reviews: u8 = 0;
+        // Review of the current proof that was already obtained while refining it
+        let mut pending: Option<(Option<String>, u8)> = None;
+        for i in 0..self.iterations {
+            let span = info_span!("review_iteration", iteration = i);
+            let (review, calls) = match pending.take() {
+                Some(review) => review,
+                None => {
+                    self.verifier
+                        .review(&self.conj, &self.proof)
+                        .instrument(span.clone())
+                        .await?
+                }
+            };
+            used_reviews = used_reviews.saturating_add(calls);
+
+            let Some(r) = review else {
+                return Ok((self, Verdict::Verified(used_reviews)));
+            };
+            if i == self.iterations - 1 {
+                info!("Refinement failed after {} trials.", self.iterations);
+                return Ok((self, Verdict::Failed(r)));
+            }
+            info!("A flaw was found in the proof, trying to refine.");
+            let refinement = self
+                .verifier
+                .refine(&self.conj, &self.proof, &r)
+                .instrument(span)
+                .await?;
+            let raw_refinement = refinement.raw;
+            if find_box(&raw_refinement).is_none() {
+                error!("Found a format error in refinement, end this step.");
+                return Ok((self, Verdict::FormatError));
+            }
+            if let Some(n_conj) = refined_statement(&raw_refinement) {
+                self.counterexamples.push(
+                    MemoryBlock::new()
+                        .memtype(MemType::Counterexample)
+                        .content(&self.conj)
+                        .proof(extract_component(&raw_refinement, "proof").unwrap_or_default()),
+                );
+                self.conj = n_conj;
+            }
+            if let Some(n_proof) = extract_component(&raw_refinement, "proof") {
+                self.proof = n_proof;
+            }
+            pending = refinement.review;
+        }
+        Ok((self, Verdict::FormatError))
+    }
+}
+
+/// Review of a node of a proof path: its id, the flaw found if any and the reviewer calls used
+type NodeReview = (MemoryId, Option<String>, u8);
+
+/// A final proof of the problem whose verification was still in progress
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingProof {
+    proof: String,
+    deps: Vec<MemoryId>,
+    iteration: u8,
+}
+
+/// A node whose proof path was being reviewed and refined in graph mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingReview {
+    memid: MemoryId,
+    iteration: u8,
+}
+
+/// Session state written after every step and every memory change, and after every iteration
+/// of a pending verification, from which `--resume` continues. The memory is borrowed when the checkpoint is
+/// written and owned when it is read back.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<M> {
+    step: u32,
+    #[serde(default)]
+    pending_proof: Option<PendingProof>,
+    #[serde(default)]
+    pending_review: Option<PendingReview>,
+    #[serde(default)]
+    usage: Usage,
+    #[serde(default)]
+    strategy: Option<serde_json::Value>,
+    memory: M,
+}
+
+/// Which direction of the problem a session settled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Proved,
+    Disproved,
+    Unresolved,
+    BudgetExhausted,
+    Interrupted,
+}
+
+impl Outcome {
+    /// Project status recorded when a remote session ends with this outcome
+    pub fn status(&self) -> &'static str {
+        match self {
+            Outcome::Proved => "solved",
+            Outcome::Disproved => "disproved",
+            Outcome::Unresolved => "ended",
+            Outcome::BudgetExhausted => "budget_exhausted",
+            Outcome::Interrupted => "interrupted",
+        }
+    }
+    /// Whether the step that ended with this outcome ran to its end, so that a resumed session
+    /// continues after it instead of repeating it
+    fn completes_step(&self) -> bool {
+        !matches!(self, Outcome::Interrupted | Outcome::BudgetExhausted)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResearchSessionConfig {
     title: String,
     logdir: PathBuf,
@@ -45,11 +485,32 @@ pub struct ResearchSessionConfig {
     currect_steps: u32,
     problem: String,
     context: String,
-    resume: bool,             // resume from existing explore trajectory
-    reformat: bool,           // reformat conjectures and proofs after exploration
-    streaming: bool,          // streaming output in exploration
-    theorem_graph_mode: bool, // whether to use theorem graph mode
-    reasoning_effort: String, // new field for reasoning_effort
+    resume: bool,                // resume from existing explore trajectory
+    reformat: bool,              // reformat conjectures and proofs after exploration
+    streaming: bool,             // streaming output in exploration
+    theorem_graph_mode: bool,    // whether to use theorem graph mode
+    strategy: String,            // exploration strategy, derived from theorem_graph_mode if empty
+    disproof: bool,              // run a second track aiming at the negation of the problem
+    skeptic: bool,               // attack new conjectures for counterexamples before review
+    reasoning_effort: String,    // new field for reasoning_effort
+    dedup_threshold: f64, // fuzzy text similarity above which a new lemma is merged, 0 for exact restatements only
+    context_budget: usize, // estimated token budget of agent contexts, 0 for unlimited
+    consolidation_interval: u32, // steps between research digests, 0 to disable
+    digest_threshold: usize, // number of lemmas after which the explorer reads the digest
+    beam_width: u8,       // explorations sampled per graph step, 1 to disable beam mode
+    beam_keep: u8,        // best sampled explorations inserted into memory
+    refinement_candidates: u8, // refinements sampled and reviewed in parallel, 1 to disable
+    token_budget: u64,    // total tokens of API calls, 0 for unlimited
+    cost_budget: f64,     // estimated cost in USD, 0 for unlimited
+    time_budget: u64,     // wall-clock minutes, 0 for unlimited
+    call_budget: u64,     // number of API calls without retries, 0 for unlimited
+    input_price: f64,     // USD per million prompt tokens, for cost estimates
+    output_price: f64,    // USD per million completion tokens, for cost estimates
+    #[serde(default)]
+    event_log: String, // JSONL log of agent calls and events, under logdir/logs if empty
+    #[serde(default)]
+    project_logs: String, // directory of the logs of remote projects, named by project id
+    embedding_model: String, // optional embedding model for duplicate detection
 }
 impl Default for ResearchSessionConfig {
     fn default() -> Self {
@@ -71,7 +532,26 @@ impl Default for ResearchSessionConfig {
             reformat: false,
             streaming: false,
             theorem_graph_mode: false,
+            strategy: String::new(),
+            disproof: false,
+            skeptic: true,
             reasoning_effort: String::new(),
+            dedup_threshold: 0.0,
+            context_budget: 96000,
+            consolidation_interval: 8,
+            digest_threshold: 24,
+            beam_width: 1,
+            beam_keep: 1,
+            refinement_candidates: 1,
+            token_budget: 0,
+            cost_budget: 0.0,
+            time_budget: 0,
+            call_budget: 0,
+            input_price: 0.0,
+            output_price: 0.0,
+            event_log: String::new(),
+            project_logs: String::new(),
+            embedding_model: String::new(),
         }
     }
 }
@@ -136,10 +616,91 @@ impl ResearchSessionConfig {
         self.theorem_graph_mode = tgm;
         self
     }
+    pub fn skeptic(mut self, skeptic: bool) -> Self {
+        self.skeptic = skeptic;
+        self
+    }
+    pub fn disproof(mut self, disproof: bool) -> Self {
+        self.disproof = disproof;
+        self
+    }
+    pub fn strategy(mut self, strategy: impl Into<String>) -> Self {
+        self.strategy = strategy.into();
+        self
+    }
     pub fn reasoning_effort(mut self, effort: impl Into<String>) -> Self {
         self.reasoning_effort = effort.into();
         self
     }
+    pub fn dedup_threshold(mut self, threshold: f64) -> Self {
+        self.dedup_threshold = threshold;
+        self
+    }
+    pub fn context_budget(mut self, tokens: usize) -> Self {
+        self.context_budget = tokens;
+        self
+    }
+    pub fn consolidation_interval(mut self, steps: u32) -> Self {
+        self.consolidation_interval = steps;
+        self
+    }
+    pub fn digest_threshold(mut self, lemmas: usize) -> Self {
+        self.digest_threshold = lemmas;
+        self
+    }
+    pub fn beam_width(mut self, width: u8) -> Self {
+        self.beam_width = width;
+        self
+    }
+    pub fn beam_keep(mut self, keep: u8) -> Self {
+        self.beam_keep = keep;
+        self
+    }
+    pub fn refinement_candidates(mut self, candidates: u8) -> Self {
+        self.refinement_candidates = candidates;
+        self
+    }
+    pub fn token_budget(mut self, tokens: u64) -> Self {
+        self.token_budget = tokens;
+        self
+    }
+    fn budget(&self) -> Budget {
+        Budget {
+            tokens: self.token_budget,
+            cost: self.cost_budget,
+            minutes: self.time_budget,
+            calls: self.call_budget,
+            input_price: self.input_price,
+            output_price: self.output_price,
+        }
+    }
+
+    pub fn cost_budget(mut self, cost: f64) -> Self {
+        self.cost_budget = cost;
+        self
+    }
+    pub fn time_budget(mut self, minutes: u64) -> Self {
+        self.time_budget = minutes;
+        self
+    }
+    pub fn call_budget(mut self, calls: u64) -> Self {
+        self.call_budget = calls;
+        self
+    }
+    pub fn prices(mut self, input_price: f64, output_price: f64) -> Self {
+        self.input_price = input_price;
+        self.output_price = output_price;
+        self
+    }
+    /// Write the log of a remote session to `<dir>/<project id>.jsonl` unless `event_log` is set
+    pub fn project_logs(mut self, dir: impl Into<String>) -> Self {
+        self.project_logs = dir.into();
+        self
+    }
+    pub fn embedding_model(mut self, model: impl Into<String>) -> Self {
+        self.embedding_model = model.into();
+        self
+    }
     pub fn set_current_steps(&mut self, steps: u32) -> &Self {
         self.currect_steps = steps;
         self
@@ -156,7 +717,7 @@ impl ResearchSessionConfig {
     pub fn save_configs(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let serialized_config = serde_json::to_string_pretty(self)?;
         let config_path = self.logdir.as_path().join("config.json");
-        fs::write(config_path, serialized_config)?;
+        write_atomic(&config_path, serialized_config.as_bytes())?;
         Ok(())
     }
 }
@@ -169,34 +730,116 @@ pub struct ResearchSession {
     simple_reviewer: SimpleReviewer,
     progressive_reviewer: ProgressiveReviewer,
     refiner: Refiner,
+    embedder: Option<Embedder>,
+    embeddings: HashMap<String, Vec<f32>>, // cached embeddings by content hash
+    strategy: Option<Box<dyn ExplorationStrategy>>,
+    strategy_state: Option<serde_json::Value>, // state of the strategy kept in the checkpoint
+    goal: Option<MemoryId>,                    // subgoal explored in place of the problem
+    disproof: Option<Box<ResearchSession>>,    // track exploring the negation of the problem
+    telemetry: Arc<Telemetry>,                 // API usage shared with the disproof track
+    pending_proof: Option<PendingProof>,       // final proof under verification in a linear step
+    pending_review: Option<PendingReview>,     // node under review and refinement in graph mode
+    cancel: CancellationToken, // cancelled to interrupt the session at the current step
+    observers: Observers,
+    started_at: DateTime<Utc>,
     memory: Memory,
 }
 
 impl ResearchSession {
     pub fn new(config: ResearchSessionConfig) -> Self {
+        let log = Self::open_event_log(&config);
+        if config.cost_budget > 0.0 && config.input_price == 0.0 && config.output_price == 0.0 {
+            warn!(
+                "The cost budget of ${:.2} has no effect without --input_price and --output_price",
+                config.cost_budget
+            );
+        }
+        let mut telemetry = Telemetry::new().budget(config.budget());
+        if let Some(log) = &log {
+            telemetry = telemetry.log(log.clone());
+        }
+        let mut session = Self::with_telemetry(config, Arc::new(telemetry));
+        if let Some(log) = log {
+            session.add_observer(log);
+        }
+        session
+    }
+
+    fn open_event_log(config: &ResearchSessionConfig) -> Option<Arc<JsonlLog>> {
+        // Every run of a session directory gets its own log file
+        let path = if !config.event_log.is_empty() {
+            PathBuf::from(&config.event_log)
+        } else if !config.logdir.as_os_str().is_empty() {
+            let name = format!("{}.jsonl", Utc::now().format("%Y%m%d-%H%M%S"));
+            config.logdir.join("logs").join(name)
+        } else {
+            return None;
+        };
+        Self::create_event_log(&path)
+    }
+
+    fn create_event_log(path: &Path) -> Option<Arc<JsonlLog>> {
+        match JsonlLog::create(path) {
+            Ok(log) => {
+                info!("Writing the session log to {:?}", log.path());
+                Some(Arc::new(log))
+            }
+            Err(e) => {
+                error!("Failed to create the session log {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Create a session whose API usage is recorded in the given telemetry
+    pub fn with_telemetry(config: ResearchSessionConfig, telemetry: Arc<Telemetry>) -> Self {
         info!("Initialized a ResearchSession with config: {:#?}", config);
         let explorer = Explorer::new()
+            .telemetry(telemetry.clone())
             .model(&config.proof_model)
             .streaming(config.streaming)
             .reasoning_effort(config.reasoning_effort.clone());
         let simple_reviewer = SimpleReviewer::new()
+            .telemetry(telemetry.clone())
             .model(&config.eval_model)
             .reviews(config.reviews)
             .streaming(config.streaming && (config.reviews == 1))
             .reasoning_effort(config.reasoning_effort.clone());
         let progressive_reviewer = ProgressiveReviewer::new()
+            .telemetry(telemetry.clone())
             .model(&config.eval_model)
             .max_iters(config.max_review_iters as usize)
             .reasoning_effort(config.reasoning_effort.clone());
         let refiner = Refiner::new()
+            .telemetry(telemetry.clone())
             .model(&config.proof_model)
             .streaming(config.streaming)
             .reasoning_effort(config.reasoning_effort.clone());
+        let embedder = (!config.embedding_model.is_empty()).then(|| {
+            Embedder::new()
+                .telemetry(telemetry.clone())
+                .model(&config.embedding_model)
+        });
+        let default_strategy = if config.theorem_graph_mode {
+            "graph"
+        } else {
+            "linear"
+        };
+        let strategy_name = if config.strategy.is_empty() {
+            default_strategy
+        } else {
+            config.strategy.as_str()
+        };
+        let strategy = build_strategy(strategy_name).unwrap_or_else(|e| {
+            warn!("{}, falling back to the {} strategy", e, default_strategy);
+            build_strategy(default_strategy).unwrap()
+        });
+        info!("Exploring with the {} strategy", strategy.name());
         let mut mem = Memory::new();
         if !config.context.is_empty() {
             mem.update(
                 MemoryBlock::new()
-                    .memtype("context")
+                    .memtype(MemType::Context)
                     .content(&config.context)
                     .solved(true),
             );
@@ -207,15 +850,442 @@ impl ResearchSession {
             simple_reviewer: simple_reviewer,
             progressive_reviewer: progressive_reviewer,
             refiner: refiner,
+            embedder,
+            embeddings: HashMap::new(),
+            strategy: Some(strategy),
+            strategy_state: None,
+            goal: None,
+            disproof: None,
+            telemetry,
+            pending_proof: None,
+            pending_review: None,
+            cancel: CancellationToken::new(),
+            observers: Observers::default(),
+            started_at: Utc::now(),
             memory: mem,
         }
     }
 
+    /// Token that interrupts the running step when cancelled, after which the session saves
+    /// its state and writes its reports
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    pub fn add_observer(&mut self, observer: Arc<dyn SessionObserver>) -> &Self {
+        self.observers.add(observer);
+        self
+    }
+
+    fn emit(&self, event: SessionEvent) {
+        self.observers.emit(event);
+    }
+
+    fn memory_updated(&self, memid: MemoryId) {
+        let Some(m) = self.memory.get(memid) else {
+            return;
+        };
+        self.emit(SessionEvent::MemoryUpdated {
+            memid,
+            memtype: m.memtype,
+            solved: m.is_solved(),
+            verified: self.memory.is_verified(memid),
+            content: m.content.clone(),
+            proof: m.proof.clone(),
+            comment: m.get_comment().to_string(),
+        });
+    }
+
+    fn invalidate_dependents(&mut self, memid: MemoryId) {
+        for dependent in self.memory.invalidate_dependents(memid) {
+            self.memory_updated(dependent);
+        }
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Point the explorer at a memory node to build on, or clear the focus with `None`
+    pub fn set_focus(&mut self, focus: Option<MemoryId>) {
+        let focus = focus.and_then(|id| self.memory.get(id)).map(|m| {
+            format!(
+                "#### Memory **ID: {}**\n\n{}",
+                m.get_id(),
+                m._format_with_proof_summary()
+            )
+        });
+        self.explorer.set_focus(focus);
+    }
+
+    /// Explore towards the subgoal in the given memory node, or the original problem with `None`
+    pub fn set_goal(&mut self, goal: Option<MemoryId>) {
+        let target = goal
+            .and_then(|id| self.memory.get(id))
+            .map(|m| m.content.clone());
+        self.goal = goal.filter(|_| target.is_some());
+        self.explorer
+            .set_problem(target.unwrap_or_else(|| self.config.problem.clone()));
+    }
+
+    pub fn add_open_question(&mut self, statement: impl Into<String>) -> MemoryId {
+        self.update_memory_graph(
+            MemoryBlock::new()
+                .memtype(MemType::OpenQuestion)
+                .content(statement)
+                .solved(false),
+        )
+    }
+
+    pub fn abandon_subgoal(&mut self, id: MemoryId, statement: &str) {
+        // A subgoal dropped from the plan is kept as a dead end instead of lingering as open
+        // work: a refuted subgoal becomes a counterexample and an unproved one a failed attempt.
+        // A proved lemma stays, even when it is the opposite statement of the subgoal.
+        let Some(memblock) = self.memory.get(id) else {
+            return;
+        };
+        let refuted = normalize_text(&memblock.content) != normalize_text(statement);
+        let refutation = format!("The subgoal was refuted by: {}", memblock.content);
+        if memblock.memtype == MemType::Lemma && memblock.is_solved() {
+            if refuted {
+                self.record_dead_end(
+                    MemoryBlock::new()
+                        .memtype(MemType::Counterexample)
+                        .content(statement)
+                        .proof(refutation),
+                );
+            }
+            return;
+        }
+        let Some(memblock) = self.memory.get_mut(id) else {
+            return;
+        };
+        if refuted {
+            memblock.memtype = MemType::Counterexample;
+            memblock.proof = refutation;
+        } else {
+            memblock.memtype = MemType::FailedAttempt;
+            memblock.set_comment("Dropped from the proof plan when it was revised");
+        }
+        memblock.content = statement.to_string();
+        memblock.set_solved(false);
+        info!(
+            "Kept the abandoned subgoal in memory ID {} as a dead end",
+            id
+        );
+        self.memory_updated(id);
+        self.write_checkpoint();
+    }
+
+    pub async fn plan(
+        &mut self,
+        revision: Option<String>,
+    ) -> Result<Option<(String, Vec<String>)>, Box<dyn std::error::Error + Send + Sync>> {
+        // Ask the planner for a proof skeleton, returning its explanation and the subgoals
+        info!("Planning subgoals of the problem");
+        let planner = Planner::new()
+            .telemetry(self.telemetry.clone())
+            .model(&self.config.proof_model)
+            .problem(&self.config.problem)
+            .context(self.assemble_statements("planner"))
+            .revision(revision)
+            .reasoning_effort(self.config.reasoning_effort.clone());
+        let raw_plan = planner._process().await?;
+        let subgoals = extract_all_component(&raw_plan, "subgoal");
+        if subgoals.is_empty() {
+            warn!("Found a format error in planning, no subgoals were proposed.");
+            return Ok(None);
+        }
+        let skeleton = extract_component(&raw_plan, "skeleton").unwrap_or_default();
+        Ok(Some((skeleton, subgoals)))
+    }
+
+    fn start_disproof_track(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // The disproof track shares the problem context and keeps its files in a subdirectory
+        let mut config = self.config.clone();
+        config.disproof = false;
+        config.problem = format!("{}{}", DISPROOF_PREFIX, self.config.problem);
+        if !self.config.logdir.as_os_str().is_empty() {
+            config.logdir = self.config.logdir.join("disproof");
+            fs::create_dir_all(&config.logdir)?;
+        }
+        let resume = config.resume;
+        let mut track = ResearchSession::with_telemetry(config, self.telemetry.clone());
+        track.observers = self.observers.disproof_track();
+        track.explorer.set_problem(track.config.problem.clone());
+        if resume {
+            // The telemetry is shared, so its usage is restored from the checkpoint of this
+            // session and the copy in the checkpoint of the track is ignored
+            let _ = track.resume()?;
+        }
+        if let Some(context) = self
+            .memory
+            .memory
+            .iter()
+            .find(|m| m.memtype == MemType::Context)
+            && !track
+                .memory
+                .memory
+                .iter()
+                .any(|m| m.memtype == MemType::Context)
+        {
+            track.memory.update(
+                MemoryBlock::new()
+                    .memtype(MemType::Context)
+                    .content(&context.content)
+                    .solved(true),
+            );
+        }
+        info!("Started the disproof track: {}", &track.config.problem);
+        self.disproof = Some(Box::new(track));
+        Ok(())
+    }
+
+    async fn explore_tracks(
+        &mut self,
+    ) -> Result<Outcome, Box<dyn std::error::Error + Send + Sync>> {
+        // One step of the proof track, together with the disproof track when it is enabled
+        let Some(mut track) = self.disproof.take() else {
+            return Ok(match self.explore_step().await? {
+                true => Outcome::Proved,
+                false => Outcome::Unresolved,
+            });
+        };
+        track.config.set_current_steps(self.config.currect_steps);
+        let track_step = async {
+            // A final proof the track was verifying when interrupted completes its step
+            match track.pending_proof.take() {
+                Some(pending) => track.verify_final_proof(pending).await,
+                None => track.explore_step().await,
+            }
+        };
+        // The first track to verify its proof ends the step and the other one is dropped, a
+        // failing track does not hide a proof verified by the other one
+        let (verified, proved, disproved) = {
+            let proof_step = self.explore_step();
+            tokio::pin!(proof_step, track_step);
+            let mut proved = None;
+            let mut disproved = None;
+            let verified = loop {
+                tokio::select! {
+                    result = &mut proof_step, if proved.is_none() => match result {
+                        Ok(true) => break Some(Outcome::Proved),
+                        result => proved = Some(result),
+                    },
+                    result = &mut track_step, if disproved.is_none() => match result {
+                        Ok(true) => break Some(Outcome::Disproved),
+                        result => disproved = Some(result),
+                    },
+                }
+                if proved.is_some() && disproved.is_some() {
+                    break None;
+                }
+            };
+            (verified, proved, disproved)
+        };
+        self.disproof = Some(track);
+        match (verified, proved, disproved) {
+            (Some(outcome), proved, disproved) => {
+                for e in [proved, disproved]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Result::err)
+                {
+                    warn!("Ignoring the failure of the other track: {}", e);
+                }
+                Ok(outcome)
+            }
+            (None, Some(proved), Some(disproved)) => {
+                if let (Err(_), Err(e)) = (&proved, &disproved) {
+                    warn!("The disproof track failed as well: {}", e);
+                }
+                proved.and(disproved).map(|_| Outcome::Unresolved)
+            }
+            _ => unreachable!("the loop ends once both tracks finished"),
+        }
+    }
+
+    fn proof_path(&self) -> Option<String> {
+        // Dead ends are appended to the memory as well, so the proof path ends at the last
+        // theorem or the last node under review
+        let end = self
+            .memory
+            .memory
+            .iter()
+            .rev()
+            .find(|m| m.memtype == MemType::Theorem)
+            .or_else(|| {
+                self.memory
+                    .memory
+                    .iter()
+                    .rev()
+                    .find(|m| m.memtype.needs_review())
+            })?;
+        self.memory
+            .format_deps_with_order(end.get_id(), true, true, false)
+    }
+
+    fn report(&self, outcome: Outcome) -> String {
+        let summary = match outcome {
+            Outcome::Proved => "The statement was **proved**.",
+            Outcome::Disproved => "The statement was **disproved**.",
+            Outcome::Unresolved => "The statement was neither proved nor disproved.",
+            Outcome::BudgetExhausted => {
+                "The session ran out of its budget before the statement was settled."
+            }
+            Outcome::Interrupted => "The session was interrupted before the statement was settled.",
+        };
+        let proof_path = match outcome {
+            Outcome::Proved => self.proof_path(),
+            Outcome::Disproved => self.disproof.as_ref().and_then(|t| t.proof_path()),
+            Outcome::Unresolved | Outcome::BudgetExhausted | Outcome::Interrupted => None,
+        };
+        let mut report = format!(
+            "# Research Report of AIM\n\n{}\n\n## Problem\n\n{}\n",
+            summary, self.config.problem
+        );
+        if let Some(proof_path) = proof_path {
+            report.push_str(&format!("\n## Proof Path\n\n{}", proof_path));
+        }
+        let usage = self.telemetry.usage();
+        report.push_str(&format!(
+            "\n## Usage\n\n- API calls: {}\n- Tokens: {} prompt, {} completion\n- Estimated cost: ${:.2}\n- Time: {} minutes\n",
+            usage.calls,
+            usage.prompt_tokens,
+            usage.completion_tokens,
+            self.cost(&usage),
+            usage.elapsed.as_secs() / 60
+        ));
+        if let Some(reason) = self.budget_exhausted() {
+            report.push_str(&format!("- Budget: {}\n", reason));
+        }
+        report
+    }
+
+    fn cost(&self, usage: &Usage) -> f64 {
+        self.telemetry.cost(usage)
+    }
+
+    fn budget_exhausted(&self) -> Option<String> {
+        // Describe the first budget the session has used up, if any
+        self.telemetry.exhausted()
+    }
+
+    fn step_outcome(
+        &self,
+        result: Result<Outcome, Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Result<Outcome, Box<dyn std::error::Error + Send + Sync>> {
+        // Agent calls fail once the budget is exhausted, which ends the session instead of
+        // failing it. Nothing the failed calls were checking has been accepted.
+        match result {
+            Err(e) if self.budget_exhausted().is_some() => {
+                warn!("The budget ran out during the step: {}", e);
+                Ok(Outcome::BudgetExhausted)
+            }
+            result => result,
+        }
+    }
+
+    fn write_manifest(&self, status: &str, finished: bool) {
+        // Written per run under `manifests/`, as the latest `manifest.json` of the session
+        // directory and as a record of the session log
+        let mut nodes: BTreeMap<String, usize> = BTreeMap::new();
+        for m in &self.memory.memory {
+            *nodes.entry(m.memtype.to_string()).or_default() += 1;
+        }
+        let usage = self.telemetry.usage();
+        let manifest = RunManifest {
+            aim_version: manifest::AIM_VERSION,
+            git_commit: manifest::GIT_COMMIT,
+            started_at: self.started_at,
+            finished_at: finished.then(Utc::now),
+            status,
+            config: &self.config,
+            prompt_hashes: manifest::prompt_hashes(),
+            roles: self.telemetry.roles(),
+            nodes,
+            verified_nodes: self
+                .memory
+                .memory
+                .iter()
+                .filter(|m| self.memory.is_verified(m.get_id()))
+                .count(),
+            usage,
+            cost: self.cost(&usage),
+        };
+        self.telemetry.write_log("manifest", &manifest);
+        if self.config.logdir.as_os_str().is_empty() {
+            return;
+        }
+        let json = match serde_json::to_vec_pretty(&manifest) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Failed to serialize the manifest: {}", e);
+                return;
+            }
+        };
+        let manifests_dir = self.config.logdir.as_path().join("manifests");
+        let run_path =
+            manifests_dir.join(format!("{}.json", self.started_at.format("%Y%m%dT%H%M%SZ")));
+        let latest_path = self.config.logdir.as_path().join("manifest.json");
+        if let Err(e) = fs::create_dir_all(&manifests_dir) {
+            error!("Failed to create {:?}: {}", manifests_dir, e);
+        }
+        for path in [run_path, latest_path] {
+            if let Err(e) = write_atomic(&path, &json) {
+                error!("Failed to write the manifest {:?}: {}", path, e);
+            }
+        }
+    }
+
+    fn write_report(&self, outcome: Outcome) -> std::io::Result<()> {
+        let report_path = self.config.logdir.as_path().join("report.md");
+        info!("Saving the research report to path: {:#?}", report_path);
+        write_atomic(&report_path, self.report(outcome).as_bytes())
+    }
+
+    pub async fn propose_variants(
+        &mut self,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        // Ask for simpler variants of the problem to be explored before the problem itself
+        info!("Proposing simpler variants of the problem");
+        let generator = VariantGenerator::new()
+            .telemetry(self.telemetry.clone())
+            .model(&self.config.proof_model)
+            .problem(&self.config.problem)
+            .reasoning_effort(self.config.reasoning_effort.clone());
+        let raw_variants = generator._process().await?;
+        let variants = extract_all_component(&raw_variants, "variant");
+        if variants.is_empty() {
+            warn!("Found a format error in variant generation, no variants were proposed.");
+        }
+        Ok(variants)
+    }
+
+    pub async fn explore_step(&mut self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // The strategy drives the session, so it is taken out for the duration of the step
+        let mut strategy = self
+            .strategy
+            .take()
+            .ok_or("Exploration strategy is already running")?;
+        let result = strategy.step(self).await;
+        self.strategy_state = strategy.state();
+        self.strategy = Some(strategy);
+        result
+    }
+
+    pub fn save_strategy_state(&mut self, state: Option<serde_json::Value>) {
+        // Called by strategies whose state changed in the middle of a step
+        self.strategy_state = state;
+        self.write_checkpoint();
+    }
+
     pub async fn load_context(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let context_path = self.config.logdir.as_path().join("context.md");
         if !context_path.exists() {
             info!("No context provided to this problem. Generating context via LLM...");
             let generator = ContextGenerator::new()
+                .telemetry(self.telemetry.clone())
                 .model(&self.config.proof_model)
                 .problem(&self.config.problem)
                 .reasoning_effort(self.config.reasoning_effort.clone());
@@ -227,7 +1297,7 @@ impl ResearchSession {
 
             self.memory.update(
                 MemoryBlock::new()
-                    .memtype("context")
+                    .memtype(MemType::Context)
                     .content(context)
                     .solved(true),
             );
@@ -237,48 +1307,140 @@ impl ResearchSession {
         info!("loaded problem context: {:?}", &context);
         self.memory.update(
             MemoryBlock::new()
-                .memtype("context")
+                .memtype(MemType::Context)
                 .content(context)
                 .solved(true),
         );
         Ok(())
     }
 
-    pub fn resume(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// Restore the state of an earlier run, returning the API usage recorded in its checkpoint
+    pub fn resume(&mut self) -> Result<Option<Usage>, Box<dyn std::error::Error + Send + Sync>> {
+        let checkpoint_path = self.config.logdir.as_path().join("checkpoint.json");
+        if checkpoint_path.exists() {
+            let checkpoint_json = fs::read_to_string(checkpoint_path)?;
+            let checkpoint: Checkpoint<Memory> = serde_json::from_str(&checkpoint_json)?;
+            info!(
+                "Resumed from the checkpoint at step {} with {} memory blocks",
+                checkpoint.step,
+                checkpoint.memory.memory.len()
+            );
+            self.memory = checkpoint.memory;
+            self.config.set_current_steps(checkpoint.step);
+            self.pending_proof = checkpoint.pending_proof;
+            self.pending_review = checkpoint.pending_review;
+            if let Some(state) = &checkpoint.strategy
+                && let Some(strategy) = self.strategy.as_mut()
+            {
+                strategy.restore(state.clone());
+            }
+            self.strategy_state = checkpoint.strategy;
+            return Ok(Some(checkpoint.usage));
+        }
         let memory_path = self.config.logdir.as_path().join("memory.json");
         if !memory_path.exists() {
             warn!(
                 "History memory does not exist in session: {:#?}!",
                 &memory_path
             );
-            return Ok(());
+            return Ok(None);
         }
         let memory_json = fs::read_to_string(memory_path)?;
         let nmemory: Memory = serde_json::from_str(&memory_json)?;
         info!("Resumed Existing Memories: {:#?}", &nmemory);
         self.memory = nmemory;
-        Ok(())
+        Ok(None)
+    }
+
+    fn write_checkpoints(&mut self) {
+        // Checkpoint this session and its disproof track at the current step
+        let step = self.config.currect_steps;
+        if let Some(track) = self.disproof.as_mut() {
+            track.config.set_current_steps(step);
+            track.write_checkpoint();
+        }
+        self.write_checkpoint();
+    }
+
+    fn write_checkpoint(&self) {
+        // Written after every memory change, a resumed session repeats at most the rest of the
+        // step. Remote sessions keep their state in the database instead
+        if self.config.logdir.as_os_str().is_empty() {
+            return;
+        }
+        let checkpoint = Checkpoint {
+            step: self.config.currect_steps,
+            pending_proof: self.pending_proof.clone(),
+            pending_review: self.pending_review.clone(),
+            usage: self.telemetry.usage(),
+            strategy: self.strategy_state.clone(),
+            memory: &self.memory,
+        };
+        let checkpoint_path = self.config.logdir.as_path().join("checkpoint.json");
+        let result = serde_json::to_vec(&checkpoint)
+            .map_err(std::io::Error::other)
+            .and_then(|json| write_atomic(&checkpoint_path, &json));
+        if let Err(e) = result {
+            error!(
+                "Failed to write the checkpoint {:?}: {}",
+                checkpoint_path, e
+            );
+        }
     }
 
     pub async fn review_mems(
         &mut self,
-        ids: &Vec<usize>,
-    ) -> Option<Vec<(usize, Option<String>, u8)>> {
+        ids: &Vec<MemoryId>,
+    ) -> Result<Vec<NodeReview>, Box<dyn std::error::Error + Send + Sync>> {
+        // Fails when any node could not be reviewed, so that no unreviewed node is accepted
         info!("Start verifying proof path with {} nodes", ids.len());
-        let mut tasks: JoinSet<(usize, Option<String>, u8)> = JoinSet::new();
-        let mut res: Vec<(usize, Option<String>, u8)> = Vec::new();
+        let mut tasks: JoinSet<Result<NodeReview, Box<dyn std::error::Error + Send + Sync>>> =
+            JoinSet::new();
+        let mut res: Vec<NodeReview> = Vec::new();
+        let max_reviews = if self.config.reviewer == "progressive" {
+            MAX_PROGRESSIVE_REVIEWS_PER_NODE
+        } else {
+            MAX_REVIEWS_PER_NODE
+        };
         for i in ids {
             let mem_id = *i;
-            let memblock = &self.memory.memory[*i];
+            let Some(memblock) = self.memory.get(*i) else {
+                continue;
+            };
+            let span = info_span!("node_review", node = mem_id);
             let comment = memblock.get_comment().to_string();
-            let memtype = memblock.memtype.to_string();
-            let num_reviews = memblock.get_reviews();
+            let memtype = memblock.memtype;
+            let verified = self.memory.is_verified(*i);
+            // Proofs are reviewed in several passes until enough reviews of the same content pass
+            let settled = verified && memblock.get_reviews() >= max_reviews;
             let context = self.memory.format_deps(*i, false, false);
             let conjecture = memblock.content.clone();
             let proof = memblock.proof.clone();
 
-            if self.config.reviewer == "progressive" {
+            if memtype == MemType::Definition {
+                let mut reviewer = DefinitionReviewer::new()
+                    .telemetry(self.telemetry.clone())
+                    .model(&self.config.eval_model)
+                    .reasoning_effort(self.config.reasoning_effort.clone());
+                if let Some(ctx) = context {
+                    reviewer.set_context(ctx);
+                }
+                reviewer.set_definition(conjecture);
+                tasks.spawn(
+                    async move {
+                        if verified {
+                            return Ok((mem_id, None, 0));
+                        }
+                        if !comment.is_empty() {
+                            return Ok((mem_id, Some(comment), 0));
+                        }
+                        Ok((mem_id, reviewer.check().await?, 1))
+                    }
+                    .instrument(span),
+                );
+            } else if self.config.reviewer == "progressive" {
                 let mut reviewer = ProgressiveReviewer::new()
+                    .telemetry(self.telemetry.clone())
                     .model(&self.config.eval_model)
                     .max_iters(self.config.max_review_iters as usize)
                     .reasoning_effort(self.config.reasoning_effort.clone());
@@ -288,18 +1450,23 @@ impl ResearchSession {
                 reviewer.set_conjecture(conjecture);
                 reviewer.set_proof(proof);
                 let arc_reviewer = Arc::new(reviewer);
-                tasks.spawn(async move {
-                    if memtype == "context" || num_reviews >= MAX_PROGRESSIVE_REVIEWS_PER_NODE {
-                        return (mem_id, None, 0);
-                    }
-                    if !comment.is_empty() {
-                        return (mem_id, Some(comment), 0);
+                tasks.spawn(
+                    async move {
+                        if !memtype.needs_review() || settled {
+                            return Ok((mem_id, None, 0));
+                        }
+                        if !comment.is_empty() {
+                            return Ok((mem_id, Some(comment), 0));
+                        }
+                        let ProgressiveReviewResult { review, api_calls } =
+                            arc_reviewer.verify().await?;
+                        Ok((mem_id, review, api_calls))
                     }
-                    let ProgressiveReviewResult { review, api_calls } = arc_reviewer.verify().await;
-                    (mem_id, review, api_calls)
-                });
+                    .instrument(span),
+                );
             } else {
                 let mut reviewer = SimpleReviewer::new()
+                    .telemetry(self.telemetry.clone())
                     .model(&self.config.eval_model)
                     .reviews(self.config.reviews)
                     .streaming(false)
@@ -311,39 +1478,48 @@ impl ResearchSession {
                 reviewer.set_conjecture(conjecture);
                 reviewer.set_proof(proof);
                 let arc_reviewer = Arc::new(reviewer);
-                tasks.spawn(async move {
-                    if memtype == "context" || num_reviews >= MAX_REVIEWS_PER_NODE {
-                        return (mem_id, None, 0);
-                    }
-                    if !comment.is_empty() {
-                        return (mem_id, Some(comment), 0);
+                tasks.spawn(
+                    async move {
+                        if !memtype.needs_review() || settled {
+                            return Ok((mem_id, None, 0));
+                        }
+                        if !comment.is_empty() {
+                            return Ok((mem_id, Some(comment), 0));
+                        }
+                        let review = arc_reviewer.pverify().await?;
+                        Ok((mem_id, review, configured_reviews))
                     }
-                    let review = arc_reviewer.pverify().await;
-                    (mem_id, review, configured_reviews)
-                });
+                    .instrument(span),
+                );
             }
         }
         while let Some(task_result) = tasks.join_next().await {
             match task_result {
-                Ok(review) => res.push(review),
+                Ok(Ok(review)) => res.push(review),
+                Ok(Err(e)) => {
+                    tasks.shutdown().await;
+                    return Err(e);
+                }
                 Err(e) => {
                     error!("Task failed: {:#?}", e);
+                    tasks.shutdown().await;
+                    return Err(e.into());
                 }
             }
         }
-        Some(res)
+        Ok(res)
     }
 
     pub async fn backtrace_review_from(
         &mut self,
-        id: usize,
+        id: MemoryId,
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
         // Obtain proof path ids in decreasing order
         let proof_path_ids = self.memory.get_proof_path_ids(id, true);
         info!("Start reviewing the proof path: {:?}", &proof_path_ids);
-        let reviews = self.review_mems(&proof_path_ids).await.unwrap_or_default();
+        let reviews = self.review_mems(&proof_path_ids).await?;
         info!("Obtained {} reviews in the proof path", reviews.len());
-        let reviews_by_id: std::collections::HashMap<usize, (Option<String>, u8)> = reviews
+        let reviews_by_id: std::collections::HashMap<MemoryId, (Option<String>, u8)> = reviews
             .into_iter()
             .map(|(id, review, api_calls)| (id, (review, api_calls)))
             .collect();
@@ -352,17 +1528,26 @@ impl ResearchSession {
         let mut path_correctness = true;
         // iterate through proof path in the derivation order
         for i in proof_path_ids.iter() {
-            let mem = &mut self.memory.memory[*i];
-            if mem.memtype == "context" {
+            let Some(mem) = self.memory.get_mut(*i) else {
+                continue;
+            };
+            if !mem.memtype.needs_review() {
                 continue;
-            } // eliminate the given context
+            } // eliminate the given context and other unreviewed blocks
             let (rev, api_calls) = reviews_by_id.get(i).cloned().unwrap_or((None, 0));
+            if rev.is_some() || api_calls > 0 {
+                self.observers.emit(SessionEvent::ReviewVerdict {
+                    statement: mem.content.clone(),
+                    review: rev.clone(),
+                });
+            }
             let max_reviews = if self.config.reviewer == "progressive" {
                 MAX_PROGRESSIVE_REVIEWS_PER_NODE
             } else {
                 MAX_REVIEWS_PER_NODE
             };
             mem.set_reviews((mem.get_reviews() + api_calls).min(max_reviews));
+            let refuted = rev.is_some();
             if let Some(r) = rev {
                 // Found a flaw in one memblock
                 path_correctness = false;
@@ -370,10 +1555,22 @@ impl ResearchSession {
                 mem.set_reviews(0);
             }
             mem.set_solved(path_correctness);
+            if refuted {
+                mem.set_verified_hash(String::new());
+                // Everything built upon a flawed memblock can no longer be trusted
+                self.invalidate_dependents(*i);
+            } else if api_calls > 0 {
+                // Record the exact content this review was issued for
+                self.memory.mark_verified(*i);
+            }
+            let Some(mem) = self.memory.get_mut(*i) else {
+                continue;
+            };
 
             // If this memory block is now solved and has a proof, generate a proof summary
             if mem.is_solved() && !mem.proof.is_empty() && mem.proof_summary.is_empty() {
                 let summarizer = ProofSummarizer::new()
+                    .telemetry(self.telemetry.clone())
                     .model(self.config.reform_model.clone())
                     .conjecture(mem.content.clone())
                     .proof(mem.proof.clone())
@@ -392,19 +1589,23 @@ impl ResearchSession {
                 }
             }
             debug!("Modified memblock: {:#?}", &mem);
+            self.memory_updated(*i);
         }
+        self.write_checkpoint();
         Ok(path_correctness)
     }
 
     pub async fn backtrace_refine_from(
         &mut self,
-        id: usize,
+        id: MemoryId,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let proof_path_ids = self.memory.get_proof_path_ids(id, true);
         info!("Start refining the proof path: {:?}", &proof_path_ids);
-        let mut tasks: JoinSet<(usize, String)> = JoinSet::new();
+        let mut tasks: JoinSet<(MemoryId, Refinement)> = JoinSet::new();
         for i in proof_path_ids {
-            let memblock = &self.memory.memory[i];
+            let Some(memblock) = self.memory.get(i) else {
+                continue;
+            };
             if memblock.is_solved() {
                 continue;
             }
@@ -412,33 +1613,58 @@ impl ResearchSession {
             if review.is_empty() {
                 continue;
             }
-            let mut refiner = Refiner::new()
-                .model(&self.config.proof_model)
-                .streaming(false);
+            let mut verifier = Verifier::new(&self.config, &self.telemetry, &self.observers);
             if let Some(context) = self.memory.format_deps(i, false, false) {
-                refiner.set_context(context);
-            }
-            refiner.set_conjecture(&memblock.content);
-            refiner.set_proof(&memblock.proof);
-            refiner.set_review(review);
-            tasks.spawn(async move {
-                // refiner will return a empty string on error
-                (i, refiner._process().await.unwrap_or_default())
-            });
+                verifier.set_context(&context);
+            }
+            let conj = memblock.content.clone();
+            let proof = memblock.proof.clone();
+            let review = review.to_string();
+            tasks.spawn(
+                async move {
+                    // an empty refinement stands for an error of the refiner
+                    let refinement =
+                        verifier
+                            .refine(&conj, &proof, &review)
+                            .await
+                            .unwrap_or(Refinement {
+                                raw: String::new(),
+                                review: None,
+                            });
+                    (i, refinement)
+                }
+                .instrument(info_span!("node_refinement", node = i)),
+            );
         }
         while let Some(res) = tasks.join_next().await {
-            if let Ok((memid, reproof)) = res {
-                let memblock = &mut self.memory.memory[memid];
+            if let Ok((memid, refinement)) = res {
+                let reproof = refinement.raw;
+                let Some(memblock) = self.memory.get_mut(memid) else {
+                    continue;
+                };
                 info!(
                     "One refinement complete for conjecture: {}.",
                     memblock.content
                 );
+                let mut refuted: Option<MemoryBlock> = None;
                 if !reproof.is_empty() {
                     memblock.set_comment(String::new());
                     memblock.set_proof_summary(String::new());
                     if let Some(judgement) = find_box(&reproof) {
                         if judgement == "false" {
-                            if let Some(n_conj) = extract_component(&reproof, "conjecture") {
+                            if let Some(n_conj) = extract_component(&reproof, "conjecture")
+                                && n_conj != memblock.content
+                            {
+                                // The proof of the opposite statement disproves the old one
+                                refuted = Some(
+                                    MemoryBlock::new()
+                                        .memtype(MemType::Counterexample)
+                                        .content(&memblock.content)
+                                        .proof(
+                                            extract_component(&reproof, "proof")
+                                                .unwrap_or_default(),
+                                        ),
+                                );
                                 memblock.content = n_conj;
                             }
                         }
@@ -447,50 +1673,324 @@ impl ResearchSession {
                         }
                     }
                 }
+                // Keep the review of the chosen candidate so that it is not repeated
+                if !reproof.is_empty()
+                    && let Some((review, calls)) = refinement.review
+                {
+                    memblock.set_reviews(calls);
+                    if let Some(r) = review {
+                        memblock.set_comment(r);
+                    } else {
+                        self.memory.mark_verified(memid);
+                    }
+                }
+                self.memory_updated(memid);
+                if let Some(refuted) = refuted {
+                    info!("The statement of memory ID {} has changed", memid);
+                    self.record_dead_end(refuted);
+                    self.invalidate_dependents(memid);
+                }
             }
         }
+        self.write_checkpoint();
         Ok(())
     }
 
-    fn update_memory(&mut self, nmemory: MemoryBlock) {
+    fn update_memory(&mut self, nmemory: MemoryBlock) -> MemoryId {
         if let Ok(mem_str) = serde_json::to_string_pretty(&nmemory) {
             info!("Session Memory Updated with: {}", mem_str);
         }
-        self.memory.update(nmemory);
-        if let Some(context) = self.memory.format_all_with_proof_summary(true) {
-            self.explorer.set_context(&context);
+        let lemma = (nmemory.memtype == MemType::Lemma).then(|| nmemory.content.clone());
+        let memid = self.memory.update(nmemory);
+        if let Some(statement) = lemma {
+            self.emit(SessionEvent::LemmaAdded { memid, statement });
+        }
+        self.memory_updated(memid);
+        self.write_checkpoint();
+        memid
+    }
+
+    fn assemble_context(&self, role: &str, goal: &str, focus: &[MemoryId]) -> Option<String> {
+        // Select the memory blocks most relevant to the goal within the context budget
+        let selection = self
+            .memory
+            .select_context(goal, focus, self.config.context_budget);
+        self.report_selection(role, selection)
+    }
+
+    fn assemble_statements(&self, role: &str) -> Option<String> {
+        // Statements of the memory most relevant to the problem within the context budget
+        let selection = self
+            .memory
+            .select_statements(&self.config.problem, self.config.context_budget);
+        self.report_selection(role, selection)
+    }
+
+    fn report_selection(&self, role: &str, selection: ContextSelection) -> Option<String> {
+        if selection.omitted.is_empty() {
+            debug!("Context for {}: {}", role, selection.report());
+        } else {
+            info!(
+                "Context for {} exceeds the budget of {} tokens, {}",
+                role,
+                self.config.context_budget,
+                selection.report()
+            );
+        }
+        selection.text.filter(|text| !text.is_empty())
+    }
+
+    fn explorer_context(&self) -> Option<String> {
+        // Once the lemma list grows too long, the explorer reads the digest instead
+        let lemmas = self
+            .memory
+            .memory
+            .iter()
+            .filter(|m| m.memtype == MemType::Lemma && m.is_solved())
+            .count();
+        if lemmas > self.config.digest_threshold
+            && let Some(context) = self.memory.format_with_digest()
+        {
+            debug!(
+                "Explorer reads the research digest in place of {} lemmas",
+                lemmas
+            );
+            return Some(context);
+        }
+        self.assemble_context("explorer", &self.config.problem, &[])
+    }
+
+    async fn consolidate(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Summarize the current memory into the research digest
+        let Some(statements) = self.assemble_statements("consolidator") else {
+            return Ok(());
+        };
+        info!("Consolidating memory into a research digest");
+        let consolidator = Consolidator::new()
+            .telemetry(self.telemetry.clone())
+            .model(&self.config.proof_model)
+            .problem(&self.config.problem)
+            .memory(statements)
+            .previous_digest(self.memory.digest().map(|m| m.content.clone()))
+            .reasoning_effort(self.config.reasoning_effort.clone());
+        let raw_digest = consolidator._process().await?;
+        match extract_component(&raw_digest, "digest") {
+            Some(digest) => {
+                let memid = self.memory.set_digest(digest);
+                info!("Research digest updated in memory ID {}", memid);
+                self.memory_updated(memid);
+                self.write_checkpoint();
+            }
+            None => warn!("Found a format error in consolidation, keeping the previous digest."),
+        }
+        Ok(())
+    }
+
+    async fn maybe_consolidate(&mut self) {
+        let interval = self.config.consolidation_interval;
+        let step = self.config.currect_steps;
+        if interval > 0
+            && step > 0
+            && step.is_multiple_of(interval)
+            && let Err(e) = self.consolidate().await
+        {
+            // The digest only condenses the memory, a failed update keeps the previous one
+            warn!(
+                "Failed to consolidate memory, keeping the previous digest: {}",
+                e
+            );
+        }
+    }
+
+    fn focus_verifiers(&mut self, goal: &str, focus: &[MemoryId]) {
+        // Give reviewers and the refiner the context relevant to the statement under review
+        if let Some(context) = self.assemble_context("verification", goal, focus) {
             self.simple_reviewer.set_context(&context);
             self.progressive_reviewer.set_context(&context);
             self.refiner.set_context(&context);
         }
     }
 
-    fn update_memory_graph(&mut self, nmemory: MemoryBlock) {
+    fn verifier(&self, streaming: bool) -> Verifier {
+        // Verifier carrying the reviewers and refiner focused on the current statement
+        Verifier {
+            reviewer: self.config.reviewer.clone(),
+            reviews: self.config.reviews,
+            candidates: self.config.refinement_candidates,
+            simple_reviewer: self.simple_reviewer.clone(),
+            progressive_reviewer: self.progressive_reviewer.clone(),
+            refiner: self.refiner.clone().streaming(streaming),
+            observers: self.observers.clone(),
+        }
+    }
+
+    fn update_memory_graph(&mut self, nmemory: MemoryBlock) -> MemoryId {
         if let Ok(mem_str) = serde_json::to_string_pretty(&nmemory) {
             info!("Session Memory Graph Updated with: {}", mem_str);
         }
-        self.memory.update(nmemory);
+        let lemma = (nmemory.memtype == MemType::Lemma).then(|| nmemory.content.clone());
+        let memid = self.memory.update(nmemory);
+        if let Some(statement) = lemma {
+            self.emit(SessionEvent::LemmaAdded { memid, statement });
+        }
+        self.memory_updated(memid);
+        self.write_checkpoint();
+        memid
+    }
+
+    async fn merge_duplicate(&mut self, nmemory: &MemoryBlock) -> Option<MemoryId> {
+        // Find an existing node restating the new block and cite it instead of inserting a copy
+        let mut duplicate = self
+            .memory
+            .find_duplicate(nmemory, self.config.dedup_threshold);
+        if duplicate.is_none() && self.embedder.is_some() {
+            duplicate = self.find_duplicate_by_embedding(nmemory).await;
+        }
+        let memid = duplicate?;
+        info!(
+            "New {} duplicates memory ID {}, merging: {}",
+            nmemory.memtype, memid, nmemory.content
+        );
+        let existing = self.memory.get_mut(memid)?;
+        existing.cite();
+        debug!(
+            "Memory ID {} is now cited {} times",
+            memid,
+            existing.get_citations()
+        );
+        self.memory_updated(memid);
+        self.write_checkpoint();
+        Some(memid)
+    }
+
+    async fn find_duplicate_by_embedding(&mut self, nmemory: &MemoryBlock) -> Option<MemoryId> {
+        let embedder = self.embedder.as_ref()?;
+        let candidates = self.memory.dedup_candidates(nmemory);
+        if candidates.is_empty() {
+            return None;
+        }
+        let mut missing: Vec<String> = candidates
+            .iter()
+            .filter_map(|id| self.memory.get(*id))
+            .map(|m| m.content.clone())
+            .chain(std::iter::once(nmemory.content.clone()))
+            .filter(|c| !self.embeddings.contains_key(&content_hash(&[c])))
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        match embedder.embed(&missing).await {
+            Ok(embeddings) => {
+                for (c, e) in missing.iter().zip(embeddings) {
+                    self.embeddings.insert(content_hash(&[c]), e);
+                }
+            }
+            Err(e) => {
+                warn!("Failed to embed memory contents: {}", e);
+                return None;
+            }
+        }
+        let target = self.embeddings.get(&content_hash(&[&nmemory.content]))?;
+        candidates
+            .into_iter()
+            .filter_map(|id| {
+                let m = self.memory.get(id)?;
+                let e = self.embeddings.get(&content_hash(&[&m.content]))?;
+                let score = cosine_similarity(target, e);
+                (score >= EMBEDDING_DUPLICATE_THRESHOLD).then_some((id, score))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id)
+    }
+
+    fn format_cited(&self, deps: &[MemoryId]) -> Option<String> {
+        // Statements of the memory blocks cited by a new conjecture
+        let cited = deps
+            .iter()
+            .filter_map(|id| self.memory.get(*id))
+            .map(|m| format!("#### Memory **ID: {}**\n\n{}\n\n", m.get_id(), m._format()))
+            .collect::<String>();
+        (!cited.is_empty()).then_some(cited)
+    }
+
+    async fn falsify(&mut self, conj: &str, deps: &[MemoryId]) -> bool {
+        // Reject a new conjecture that every skeptic sample refutes, keeping the first
+        // counterexample. A split verdict leaves the conjecture to the reviewers.
+        if !self.config.skeptic {
+            return false;
+        }
+        let mut skeptic = Skeptic::new()
+            .telemetry(self.telemetry.clone())
+            .model(&self.config.eval_model);
+        if let Some(context) = self.format_cited(deps) {
+            skeptic.set_context(context);
+        }
+        skeptic.set_conjecture(conj);
+        let Some(counterexample) = skeptic.refute().await else {
+            return false;
+        };
+        for sample in 1..SKEPTIC_SAMPLES {
+            if skeptic.refute().await.is_none() {
+                warn!(
+                    "Skeptic sample {} found no counterexample, leaving the suspect conjecture to review",
+                    sample + 1
+                );
+                return false;
+            }
+        }
+        self.record_dead_end(
+            MemoryBlock::new()
+                .memtype(MemType::Counterexample)
+                .content(conj)
+                .proof(counterexample)
+                .deps(deps.to_vec()),
+        );
+        true
+    }
+
+    fn record_dead_end(&mut self, nmemory: MemoryBlock) -> MemoryId {
+        info!(
+            "Recording a dead end ({}): {}",
+            nmemory.memtype, nmemory.content
+        );
+        let memid = self.memory.record_dead_end(nmemory);
+        self.explorer.set_dead_ends(self.memory.format_dead_ends());
+        self.memory_updated(memid);
+        self.write_checkpoint();
+        memid
     }
 
     async fn drive_remote_pipeline(
         &mut self,
-        db: &DatabaseConnection,
-        project_filter: &str,
-    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-        let mut solved_flag = false;
-        for _ in 0..self.config.steps {
-            let done = if self.config.theorem_graph_mode {
-                self.graph_step().await?
-            } else {
-                self.step().await?
-            };
+        db: &DatabaseConnection,
+        project_filter: &str,
+    ) -> Result<Outcome, Box<dyn std::error::Error + Send + Sync>> {
+        if self.config.disproof {
+            self.start_disproof_track()?;
+        }
+        let mut outcome = Outcome::Unresolved;
+        for i in self.config.currect_steps..self.config.steps {
+            if let Some(reason) = self.budget_exhausted() {
+                warn!(
+                    "Stopping the session since its budget is exhausted: {}",
+                    reason
+                );
+                outcome = Outcome::BudgetExhausted;
+                break;
+            }
+            self.config.set_current_steps(i);
+            self.emit(SessionEvent::StepStarted { step: i });
+            let result = self
+                .explore_tracks()
+                .instrument(info_span!("session_step", step = i))
+                .await;
+            outcome = self.step_outcome(result)?;
             let mem_json = serde_json::to_string(&self.memory.memory)?;
             let now = Utc::now().to_rfc3339().replace("'", "''");
             let lemmas = self
                 .memory
                 .memory
                 .iter()
-                .filter(|m| m.memtype == "lemma")
+                .filter(|m| m.memtype == MemType::Lemma)
                 .count() as i32;
             let upd_sql = format!(
                 "UPDATE projects SET memory='{}', last_active='{}', lemmas_count={} WHERE {}",
@@ -501,19 +2001,18 @@ impl ResearchSession {
             );
             db.execute(Statement::from_string(DbBackend::Sqlite, upd_sql))
                 .await?;
-            if done {
-                solved_flag = true;
+            if outcome != Outcome::Unresolved {
                 break;
             }
         }
 
-        Ok(solved_flag)
+        Ok(outcome)
     }
 
     async fn save_memory(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let memory_path = self.config.logdir.as_path().join("memory.json");
-        let memory_file = fs::File::create(memory_path)?;
-        serde_json::to_writer_pretty(memory_file, &self.memory)?;
+        let memory_json = serde_json::to_vec_pretty(&self.memory)?;
+        write_atomic(&memory_path, &memory_json)?;
         Ok(())
     }
 
@@ -535,12 +2034,17 @@ impl ResearchSession {
             .enumerate()
             .map(|(i, mem)| {
                 let formatter = Formatter::new()
+                    .telemetry(self.telemetry.clone())
                     .model(&self.config.reform_model)
                     .content(&mem.content)
+                    .kind(format!("the statement of {}", mem.memtype.describe()))
                     .reasoning_effort(self.config.reasoning_effort.clone());
                 let pb = pb.clone();
                 tokio::task::spawn(async move {
-                    let response = formatter._process().await.unwrap();
+                    let response = formatter._process().await.unwrap_or_else(|e| {
+                        warn!("Failed to format memory block {}: {}", i, e);
+                        String::new()
+                    });
                     pb.inc(1);
                     (i, response)
                 })
@@ -574,12 +2078,21 @@ impl ResearchSession {
             .enumerate()
             .map(|(i, mem)| {
                 let formatter = Formatter::new()
+                    .telemetry(self.telemetry.clone())
                     .model(&self.config.reform_model)
                     .content(&mem.proof)
+                    .kind(format!(
+                        "the {} of {}",
+                        mem.memtype.proof_env().replace('_', " "),
+                        mem.memtype.describe()
+                    ))
                     .reasoning_effort(self.config.reasoning_effort.clone());
                 let pb = pb.clone();
                 tokio::task::spawn(async move {
-                    let response = formatter._process().await.unwrap();
+                    let response = formatter._process().await.unwrap_or_else(|e| {
+                        warn!("Failed to format memory block {}: {}", i, e);
+                        String::new()
+                    });
                     pb.inc(1);
                     (i, response)
                 })
@@ -610,6 +2123,9 @@ impl ResearchSession {
         if self.config.reformat {
             let _ = self.format_memory_to_markdown().await;
         }
+        if let Some(track) = self.disproof.as_mut() {
+            Box::pin(track.format_to_markdown()).await?;
+        }
         let md_path = self.config.logdir.as_path().join("all_memory.md");
         let pp_path = self.config.logdir.as_path().join("proof_path.md");
         if let Some(memory_content) = self.memory.format_all_with_proof(false) {
@@ -617,10 +2133,7 @@ impl ResearchSession {
             let contents = format!("# Explore Trajectory of AIM\n\n{}", memory_content);
             fs::write(md_path, contents)?;
         }
-        if let Some(proof_path_content) =
-            self.memory
-                .format_deps_with_order(self.memory.memory.len() - 1, true, true, false)
-        {
+        if let Some(proof_path_content) = self.proof_path() {
             info!("Saving proof paths to path: {:#?}", pp_path);
             let contents = format!("# Complete Proof Path of AIM\n\n{}", proof_path_content);
             fs::write(pp_path, contents)?;
@@ -628,68 +2141,322 @@ impl ResearchSession {
         Ok(())
     }
 
-    pub async fn graph_step(&mut self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(context) = self.memory.format_all_with_proof_summary(true) {
-            self.explorer.set_context(&context);
+    async fn explore_once(
+        &mut self,
+    ) -> Result<Option<GraphExploration>, Box<dyn std::error::Error + Send + Sync>> {
+        // A single exploration, re-prompted when its dependency list is invalid
+        let mut feedback: Option<String> = None;
+        let mut attempt: u8 = 0;
+        loop {
+            self.explorer.set_feedback(feedback.take());
+            let raw_exploration = self.explorer._process().await?;
+            match parse_graph_exploration(&raw_exploration, &self.memory) {
+                Ok(exploration) => return Ok(Some(exploration)),
+                Err(ExplorationError::Incomplete) => return Ok(None),
+                Err(ExplorationError::InvalidDeps(diagnostics)) => {
+                    warn!("Invalid dependency in exploration: {:#?}", &diagnostics);
+                    if attempt >= MAX_DEPENDENCY_RETRIES {
+                        error!("Dependency still invalid after {} retries.", attempt);
+                        return Ok(None);
+                    }
+                    attempt += 1;
+                    feedback = Some(dependency_feedback(&diagnostics));
+                }
+            }
         }
-        let raw_exploration = self.explorer._process().await?;
-        let conj = extract_component(&raw_exploration, "conjecture").unwrap_or_default();
-        let proof = extract_component(&raw_exploration, "proof").unwrap_or_default();
-        let final_proof = extract_component(&raw_exploration, "final_proof").unwrap_or_default();
-        let deps = extract_component(&raw_exploration, "dependency").unwrap_or_default();
-        if (conj.is_empty() || proof.is_empty() || deps.is_empty())
-            && (final_proof.is_empty() || deps.is_empty())
-        {
-            error!(
-                "Incomplete response format: conjecture {}; proof {}; dependency {}; final_proof: {};",
-                !conj.is_empty(),
-                !proof.is_empty(),
-                !deps.is_empty(),
-                !final_proof.is_empty()
+    }
+
+    async fn explore_beam(
+        &mut self,
+    ) -> Result<Vec<GraphExploration>, Box<dyn std::error::Error + Send + Sync>> {
+        // Sample several explorations concurrently and keep the best ones after screening
+        info!(
+            "Sampling {} explorations, keeping the best {}",
+            self.config.beam_width, self.config.beam_keep
+        );
+        self.explorer.set_feedback(None);
+        let mut tasks: JoinSet<(usize, Option<String>)> = JoinSet::new();
+        for i in 0..self.config.beam_width as usize {
+            let persona = EXPLORER_PERSONAS[i % EXPLORER_PERSONAS.len()];
+            let explorer = self
+                .explorer
+                .clone()
+                .streaming(false)
+                .persona((!persona.is_empty()).then(|| persona.to_string()));
+            tasks.spawn(
+                async move {
+                    match explorer._process().await {
+                        Ok(raw) => (i, Some(raw)),
+                        Err(e) => {
+                            error!("Exploration sample {} failed: {}", i, e);
+                            (i, None)
+                        }
+                    }
+                }
+                .instrument(info_span!("exploration_sample", sample = i)),
             );
+        }
+        let mut samples: Vec<(usize, String)> = Vec::new();
+        while let Some(res) = tasks.join_next().await {
+            if let Ok((i, Some(raw))) = res {
+                samples.push((i, raw));
+            }
+        }
+        samples.sort_by_key(|(i, _)| *i);
+        let mut candidates: Vec<GraphExploration> = Vec::new();
+        for (i, raw) in samples {
+            match parse_graph_exploration(&raw, &self.memory) {
+                Ok(exploration) => candidates.push(exploration),
+                Err(ExplorationError::Incomplete) => warn!("Dropped incomplete sample {}", i),
+                Err(ExplorationError::InvalidDeps(diagnostics)) => {
+                    warn!(
+                        "Dropped sample {} with invalid dependency: {:#?}",
+                        i, diagnostics
+                    )
+                }
+            }
+        }
+        let keep = self.config.beam_keep.max(1) as usize;
+        if candidates.len() <= keep {
+            return Ok(candidates);
+        }
+
+        let mut tasks: JoinSet<(usize, f64)> = JoinSet::new();
+        for (i, candidate) in candidates.iter().enumerate() {
+            let screener = Screener::new()
+                .telemetry(self.telemetry.clone())
+                .model(&self.config.eval_model)
+                .problem(&self.config.problem)
+                .context(self.format_cited(&candidate.deps))
+                .candidate(candidate.describe());
+            tasks.spawn(async move { (i, screener.score().await) }.in_current_span());
+        }
+        let mut scores = vec![0.0; candidates.len()];
+        while let Some(res) = tasks.join_next().await {
+            if let Ok((i, score)) = res {
+                scores[i] = score;
+            }
+        }
+        info!("Screening scores of sampled explorations: {:?}", &scores);
+        let mut ranked: Vec<(f64, GraphExploration)> = scores.into_iter().zip(candidates).collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(ranked.into_iter().take(keep).map(|(_, c)| c).collect())
+    }
+
+    async fn review_and_refine(
+        &mut self,
+        memid: MemoryId,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        self.review_and_refine_from(memid, 0).await
+    }
+
+    async fn review_and_refine_from(
+        &mut self,
+        memid: MemoryId,
+        first_iteration: u8,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // Review the proof path of a node and refine it until it passes or iterations run out,
+        // returns whether the node ends up grounded. Each iteration is kept in the checkpoint.
+        for i in first_iteration..self.config.iterations + 1 {
+            self.pending_review = Some(PendingReview {
+                memid,
+                iteration: i,
+            });
+            self.write_checkpoint();
+            info!("Starting the {}-th iteration", i);
+            let span = info_span!("review_iteration", node = memid, iteration = i);
+            if self
+                .backtrace_review_from(memid)
+                .instrument(span.clone())
+                .await?
+            {
+                info!("backtrace review ended and the proof path is correct.");
+                break;
+            } else if i < self.config.iterations {
+                info!("Some flaws were found in this proof path");
+                self.backtrace_refine_from(memid).instrument(span).await?;
+            }
+        }
+        self.pending_review = None;
+        Ok(self.memory.is_grounded(memid))
+    }
+
+    fn record_failed_proof(&mut self, memid: MemoryId) {
+        // Keep a proof that did not survive its reviews as a dead end, with the reviews collected
+        // along its proof path
+        let reviews = self
+            .memory
+            .get_proof_path_ids(memid, true)
+            .into_iter()
+            .filter_map(|i| self.memory.get(i))
+            .map(|m| m.get_comment())
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let Some(memblock) = self.memory.get(memid) else {
+            return;
+        };
+        let failed = MemoryBlock::new()
+            .memtype(MemType::FailedAttempt)
+            .content(&memblock.content)
+            .proof(&memblock.proof)
+            .comment(reviews);
+        self.record_dead_end(failed);
+    }
+
+    async fn attempt_subgoal(
+        &mut self,
+        goal: MemoryId,
+        proof: String,
+        deps: Vec<MemoryId>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // The open question of the subgoal turns into a lemma carrying the proposed proof. It is
+        // reverted when the proof fails, unless the refiner found the subgoal itself to be false.
+        let Some(memblock) = self.memory.get_mut(goal) else {
             return Ok(false);
+        };
+        info!("Collected a proof of the subgoal in memory ID {}", goal);
+        let statement = memblock.content.clone();
+        memblock.memtype = MemType::Lemma;
+        memblock.proof = proof;
+        memblock.set_deps(deps);
+        memblock.set_solved(false);
+        memblock.set_reviews(0);
+        memblock.set_comment(String::new());
+        self.memory_updated(goal);
+        if self.review_and_refine(goal).await? {
+            info!("The subgoal in memory ID {} is proved", goal);
+            return Ok(true);
         }
-        if final_proof.is_empty() {
-            info!("Collected one new conjecture: {}", &conj);
-            self.update_memory_graph(
-                MemoryBlock::new()
-                    .memtype("lemma")
-                    .content(&conj)
-                    .proof(&proof)
-                    .deps(serde_json::from_str::<Vec<usize>>(&deps).unwrap_or_default())
-                    .solved(true)
-                    .reviews(0),
+        self.record_failed_proof(goal);
+        let Some(memblock) = self.memory.get_mut(goal) else {
+            return Ok(false);
+        };
+        if memblock.content != statement {
+            info!("The subgoal in memory ID {} was refuted", goal);
+            return Ok(false);
+        }
+        memblock.memtype = MemType::OpenQuestion;
+        memblock.proof = String::new();
+        memblock.set_deps(Vec::new());
+        memblock.set_comment(String::new());
+        self.memory_updated(goal);
+        self.write_checkpoint();
+        Ok(false)
+    }
+
+    async fn resume_theorems(&mut self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // Settle the theorem nodes left by an earlier run, returning true once one of them is
+        // verified. The latest unsolved attempt goes through the review and refine loop again,
+        // from the iteration it was interrupted at.
+        let pending = self.pending_review.clone();
+        let theorems: Vec<MemoryId> = self
+            .memory
+            .memory
+            .iter()
+            .filter(|m| m.memtype == MemType::Theorem)
+            .map(|m| m.get_id())
+            .collect();
+        if let Some(&memid) = theorems.iter().find(|id| self.memory.is_grounded(**id)) {
+            self.emit(SessionEvent::TheoremVerified { memid });
+            return Ok(true);
+        }
+        if let Some(PendingReview { memid, iteration }) = pending
+            && !theorems.contains(&memid)
+            && self.memory.get(memid).is_some()
+        {
+            // A subgoal left unproved stays open for the strategy to attempt again
+            info!(
+                "Resuming the review of memory ID {} at iteration {}",
+                memid, iteration
             );
+            self.review_and_refine_from(memid, iteration).await?;
+        }
+        let Some(&latest) = theorems.last() else {
+            self.pending_review = None;
             return Ok(false);
+        };
+        let first_iteration = pending
+            .filter(|p| p.memid == latest)
+            .map_or(0, |p| p.iteration);
+        info!(
+            "Resuming the unsolved final proof in memory ID {} before exploring further",
+            latest
+        );
+        if self.review_and_refine_from(latest, first_iteration).await? {
+            self.emit(SessionEvent::TheoremVerified { memid: latest });
+            return Ok(true);
+        }
+        self.record_failed_proof(latest);
+        Ok(false)
+    }
+
+    pub async fn graph_step(&mut self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        self.maybe_consolidate().await;
+        if let Some(context) = self.explorer_context() {
+            self.explorer.set_context(&context);
+        }
+        self.explorer.set_dead_ends(self.memory.format_dead_ends());
+        self.explorer.set_next_id(None);
+        let explorations = if self.config.beam_width > 1 {
+            self.explore_beam().await?
         } else {
+            self.explore_once().await?.into_iter().collect()
+        };
+        self.emit(SessionEvent::ExplorationReceived {
+            conjectures: explorations.iter().filter(|e| !e.conj.is_empty()).count(),
+            final_proof: explorations.iter().any(|e| !e.final_proof.is_empty()),
+        });
+        // Lemmas are inserted first, the best final proof if any is attempted afterwards
+        let mut final_attempt: Option<GraphExploration> = None;
+        for exploration in explorations {
+            if !exploration.final_proof.is_empty() {
+                final_attempt = final_attempt.or(Some(exploration));
+                continue;
+            }
+            info!("Collected one new conjecture: {}", &exploration.conj);
+            // Restatements of known nodes are merged before the skeptic spends a call on them
+            let nmemory = MemoryBlock::new()
+                .memtype(MemType::Lemma)
+                .content(&exploration.conj)
+                .proof(exploration.proof)
+                .deps(exploration.deps.clone())
+                .solved(true)
+                .reviews(0);
+            if self.merge_duplicate(&nmemory).await.is_some()
+                || self.falsify(&exploration.conj, &exploration.deps).await
+            {
+                continue;
+            }
+            self.update_memory_graph(nmemory);
+        }
+        if let Some(GraphExploration {
+            final_proof, deps, ..
+        }) = final_attempt
+        {
+            if let Some(goal) = self.goal {
+                self.attempt_subgoal(goal, final_proof, deps).await?;
+                return Ok(false);
+            }
             info!("Collected the final proof of this problem.");
-            self.update_memory_graph(
+            let memid = self.update_memory_graph(
                 MemoryBlock::new()
-                    .memtype("theorem")
+                    .memtype(MemType::Theorem)
                     .content(&self.config.problem)
                     .proof(&final_proof)
-                    .deps(serde_json::from_str::<Vec<usize>>(&deps).unwrap_or_default())
+                    .deps(deps)
                     .solved(false)
                     .reviews(0),
             );
-            let memid = self.memory.memory.len() - 1;
-            for i in 0..self.config.iterations + 1 {
-                info!("Starting the {}-th iteration", i);
-                if self.backtrace_review_from(memid).await? {
-                    info!("backtrace review ended and the proof path is correct.");
-                    break;
-                } else if i < self.config.iterations {
-                    info!("Some flaws were found in this proof path");
-                    self.backtrace_refine_from(memid).await?;
-                }
-            }
-
-            if self.memory.memory[memid].is_solved()
-                && self.memory.memory[memid].memtype == "theorem"
-                && self.memory.memory[memid].content == self.config.problem
+            if self.review_and_refine(memid).await?
+                && self.memory.get(memid).is_some_and(|m| {
+                    m.memtype == MemType::Theorem && m.content == self.config.problem
+                })
             {
+                self.emit(SessionEvent::TheoremVerified { memid });
                 return Ok(true);
             }
+            self.record_failed_proof(memid);
         }
         return Ok(false);
     }
@@ -697,177 +2464,314 @@ impl ResearchSession {
     pub async fn step(&mut self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
         // One exploration step of research session.
         // This function retures true if the problem is solved, else it will return false.
-        let raw_exploration = self.explorer._process().await?;
+        self.maybe_consolidate().await;
+        if let Some(context) = self.explorer_context() {
+            self.explorer.set_context(&context);
+        }
+        self.explorer.set_dead_ends(self.memory.format_dead_ends());
+        // Ids announced to the explorer for the conjectures of its response
+        let first_new_id = self.memory.next_id();
+        self.explorer.set_next_id(Some(first_new_id));
+        let mut feedback: Option<String> = None;
+        let mut attempt: u8 = 0;
+        let (raw_exploration, conjectures, proofs, depss) = loop {
+            self.explorer.set_feedback(feedback.take());
+            let raw_exploration = self.explorer._process().await?;
 
-        let mut conjectures = extract_all_component(&raw_exploration, "conjecture");
-        let mut proofs = extract_all_component(&raw_exploration, "proof");
-        let mut depss = extract_all_component(&raw_exploration, "dependency");
+            let conjectures = extract_all_component(&raw_exploration, "conjecture");
+            let proofs = extract_all_component(&raw_exploration, "proof");
+            let raw_depss = extract_all_component(&raw_exploration, "dependency");
 
-        if conjectures.len() != proofs.len() {
-            error!(
-                "Mismatched number of conjectures ({}) and proofs ({})",
-                conjectures.len(),
-                proofs.len()
-            );
-            debug!("Extracted conjectures: {:#?}", &conjectures);
-            debug!("Extracted proofs: {:#?}", &proofs);
+            if conjectures.len() != proofs.len() {
+                error!(
+                    "Mismatched number of conjectures ({}) and proofs ({})",
+                    conjectures.len(),
+                    proofs.len()
+                );
+                debug!("Extracted conjectures: {:#?}", &conjectures);
+                debug!("Extracted proofs: {:#?}", &proofs);
+                return Ok(false);
+            } else {
+                info!(
+                    "Successfully collected {} conjectures and proofs in exploration.",
+                    conjectures.len()
+                );
+            }
+
+            let mut diagnostics: Vec<String> = Vec::new();
+            if raw_depss.len() < conjectures.len() {
+                diagnostics.push(format!(
+                    "Found {} conjectures but only {} dependency lists, every conjecture must be followed by its dependency.",
+                    conjectures.len(),
+                    raw_depss.len()
+                ));
+            }
+            let mut depss: Vec<Vec<usize>> = Vec::new();
+            for (k, raw_deps) in raw_depss.iter().enumerate() {
+                // A dependency list may cite the conjectures before it in the same response
+                let reserved = k.min(conjectures.len());
+                match self.memory.check_new_deps(raw_deps, reserved, true) {
+                    Ok(deps) => depss.push(deps),
+                    Err(issues) => diagnostics.extend(issues),
+                }
+            }
+            if diagnostics.is_empty() {
+                self.emit(SessionEvent::ExplorationReceived {
+                    conjectures: conjectures.len(),
+                    final_proof: extract_component(&raw_exploration, "final_proof").is_some(),
+                });
+                break (raw_exploration, conjectures, proofs, depss);
+            }
+            warn!("Invalid dependency in exploration: {:#?}", &diagnostics);
+            if attempt >= MAX_DEPENDENCY_RETRIES {
+                error!("Dependency still invalid after {} retries.", attempt);
+                return Ok(false);
+            }
+            attempt += 1;
+            feedback = Some(dependency_feedback(&diagnostics));
+        };
+
+        // Conjectures are verified concurrently and inserted in their original order afterwards,
+        // unless some of them cite earlier ones, which then have to be inserted first
+        let chained = depss.iter().flatten().any(|dep| *dep >= first_new_id);
+        let concurrent = conjectures.len() > 1 && !chained;
+        // Memory ID each conjecture ended up as, `None` when it was dropped
+        let mut inserted: Vec<Option<MemoryId>> = vec![None; conjectures.len()];
+        let mut failed = false;
+        let mut tasks = JoinSet::new();
+        for (i, ((conj, proof), deps)) in conjectures
+            .into_iter()
+            .zip(proofs)
+            .zip(depss.iter())
+            .enumerate()
+        {
+            let Some(deps) = resolve_new_ids(deps, first_new_id, &inserted) else {
+                warn!(
+                    "Dropped conjecture {} citing a conjecture that was dropped",
+                    i
+                );
+                failed = true;
+                continue;
+            };
+            let candidate = MemoryBlock::new().memtype(MemType::Lemma).content(&conj);
+            if let Some(memid) = self.merge_duplicate(&candidate).await {
+                inserted[i] = Some(memid);
+                continue;
+            }
+            if self.falsify(&conj, &deps).await {
+                continue;
+            }
+            info!("Start verifying conjecture {}", i);
+            self.focus_verifiers(&conj, &deps);
+            let verification = ConjectureVerification {
+                conj,
+                proof,
+                deps,
+                iterations: self.config.iterations,
+                verifier: self.verifier(self.config.streaming && !concurrent),
+                counterexamples: Vec::new(),
+            };
+            let span = info_span!("conjecture_verification", conjecture = i);
+            if concurrent {
+                tasks.spawn(async move { (i, verification.run().await) }.instrument(span));
+            } else {
+                inserted[i] = self.settle_conjecture(verification.run().instrument(span).await?);
+                failed |= inserted[i].is_none();
+            }
+        }
+        let mut results = Vec::new();
+        while let Some(res) = tasks.join_next().await {
+            results.push(res?);
+        }
+        results.sort_by_key(|(i, _)| *i);
+        for (i, result) in results {
+            inserted[i] = self.settle_conjecture(result?);
+            failed |= inserted[i].is_none();
+        }
+        // The final proof is not verified when one of its conjectures could not be established
+        if failed {
             return Ok(false);
-        } else {
-            info!(
-                "Successfully collected {} conjectures and proofs in exploration.",
-                conjectures.len()
-            );
         }
 
-        for ((conj, proof), deps) in conjectures
-            .iter_mut()
-            .zip(proofs.iter_mut())
-            .zip(depss.iter_mut())
-        {
-            info!("Start verifying a conjecture");
-            let mut used_reviews: u8 = 0;
-            for i in 0..self.config.iterations {
-                let review = if self.config.reviewer == "progressive" {
-                    self.progressive_reviewer.set_conjecture(&*conj);
-                    self.progressive_reviewer.set_proof(&*proof);
-                    let result = self.progressive_reviewer.verify().await;
-                    used_reviews = used_reviews.saturating_add(result.api_calls);
-                    result.review
-                } else {
-                    self.simple_reviewer.set_conjecture(&*conj);
-                    self.simple_reviewer.set_proof(&*proof);
-                    let arc_reviewer = Arc::new(self.simple_reviewer.clone());
-                    used_reviews = used_reviews.saturating_add(self.config.reviews);
-                    arc_reviewer.pverify().await
+        match extract_component(&raw_exploration, "final_proof") {
+            Some(proof) => {
+                let deps = depss.last().cloned().unwrap_or_default();
+                let Some(deps) = resolve_new_ids(&deps, first_new_id, &inserted) else {
+                    return Ok(false);
+                };
+                let pending = PendingProof {
+                    proof,
+                    deps,
+                    iteration: 0,
                 };
+                self.verify_final_proof(pending).await
+            }
+            None => Ok(false),
+        }
+    }
 
-                if let Some(r) = review {
-                    // Directly end this exploration step when one conjecture fails after several
-                    // iterations
-                    if i == self.config.iterations - 1 {
-                        info!("Refinement failed after {} trials.", self.config.iterations);
-                        return Ok(false);
-                    }
-                    info!("A flaw was found in the proof, trying to refine.");
-                    self.refiner.set_conjecture(&*conj);
-                    self.refiner.set_proof(&*proof);
-                    self.refiner.set_review(r);
-                    let raw_refinement = self.refiner._process().await?;
-                    if let Some(judgement) = find_box(&raw_refinement) {
-                        if judgement == "false" {
-                            if let Some(n_conj) = extract_component(&raw_refinement, "conjecture") {
-                                *conj = n_conj;
-                            }
-                        }
-                        if let Some(n_proof) = extract_component(&raw_refinement, "proof") {
-                            *proof = n_proof;
-                        }
-                    } else {
-                        error!("Found a format error in refinement, end this step.");
-                        return Ok(false);
-                    }
-                } else {
-                    self.update_memory(
-                        MemoryBlock::new()
-                            .memtype("lemma")
-                            .content(&*conj)
-                            .proof(&*proof)
-                            .deps(serde_json::from_str::<Vec<usize>>(deps).unwrap_or_default())
-                            .solved(true)
-                            .reviews(used_reviews),
-                    );
-                    break;
-                }
+    fn settle_conjecture(
+        &mut self,
+        (verification, verdict): (ConjectureVerification, Verdict),
+    ) -> Option<MemoryId> {
+        // Insert a verified conjecture, or record why it failed. Returns its memory ID if it was
+        // inserted.
+        for counterexample in verification.counterexamples {
+            self.record_dead_end(counterexample);
+        }
+        match verdict {
+            Verdict::Verified(used_reviews) => {
+                let memid = self.update_memory(
+                    MemoryBlock::new()
+                        .memtype(MemType::Lemma)
+                        .content(verification.conj)
+                        .proof(verification.proof)
+                        .deps(verification.deps)
+                        .solved(true)
+                        .reviews(used_reviews),
+                );
+                self.memory.mark_verified(memid);
+                self.memory_updated(memid);
+                Some(memid)
+            }
+            Verdict::Failed(r) => {
+                self.record_dead_end(
+                    MemoryBlock::new()
+                        .memtype(MemType::FailedAttempt)
+                        .content(verification.conj)
+                        .proof(verification.proof)
+                        .deps(verification.deps)
+                        .comment(r),
+                );
+                None
             }
+            Verdict::FormatError => None,
         }
+    }
 
-        if let Some(mut final_proof) = extract_component(&raw_exploration, "final_proof") {
-            info!("Start verifing the final proof");
-            let mut used_reviews: u8 = 0;
-            for i in 0..self.config.iterations {
-                let review = if self.config.reviewer == "progressive" {
-                    self.progressive_reviewer
-                        .set_conjecture(&self.config.problem);
-                    self.progressive_reviewer.set_proof(&final_proof);
-                    let result = self.progressive_reviewer.verify().await;
-                    used_reviews = used_reviews.saturating_add(result.api_calls);
-                    result.review
-                } else {
-                    self.simple_reviewer.set_conjecture(&self.config.problem);
-                    self.simple_reviewer.set_proof(&final_proof);
-                    let arc_reviewer = Arc::new(self.simple_reviewer.clone());
-                    used_reviews = used_reviews.saturating_add(self.config.reviews);
-                    arc_reviewer.pverify().await
-                };
+    async fn verify_final_proof(
+        &mut self,
+        pending: PendingProof,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // Review and refine a final proof, keeping its progress in the checkpoint
+        info!("Start verifing the final proof");
+        let PendingProof {
+            proof: mut final_proof,
+            deps: final_deps,
+            iteration,
+        } = pending;
+        self.focus_verifiers(&self.config.problem.clone(), &final_deps);
+        let verifier = self.verifier(self.config.streaming);
+        let mut used_reviews: u8 = 0;
+        let mut pending: Option<(Option<String>, u8)> = None;
+        for i in iteration..self.config.iterations {
+            self.pending_proof = Some(PendingProof {
+                proof: final_proof.clone(),
+                deps: final_deps.clone(),
+                iteration: i,
+            });
+            self.write_checkpoint();
+            let span = info_span!("review_iteration", iteration = i);
+            let (review, calls) = match pending.take() {
+                Some(review) => review,
+                None => {
+                    verifier
+                        .review(&self.config.problem, &final_proof)
+                        .instrument(span.clone())
+                        .await?
+                }
+            };
+            used_reviews = used_reviews.saturating_add(calls);
 
-                if let Some(r) = review {
-                    if i == self.config.iterations - 1 {
-                        return Ok(false);
-                    }
-                    self.refiner.set_conjecture(&self.config.problem);
-                    self.refiner.set_proof(&final_proof);
-                    self.refiner.set_review(r);
-                    let raw_refinement = self.refiner._process().await?;
-                    if let Some(n_proof) = extract_component(&raw_refinement, "proof") {
-                        final_proof = n_proof;
-                    }
-                } else {
-                    self.update_memory(
+            if let Some(r) = review {
+                if i == self.config.iterations - 1 {
+                    self.pending_proof = None;
+                    self.record_dead_end(
                         MemoryBlock::new()
-                            .memtype("theorem")
+                            .memtype(MemType::FailedAttempt)
                             .content(&self.config.problem)
                             .proof(&final_proof)
-                            .deps(
-                                serde_json::from_str::<Vec<usize>>(&depss[depss.len() - 1])
-                                    .unwrap_or_default(),
-                            )
-                            .solved(true)
-                            .reviews(used_reviews),
+                            .deps(final_deps.clone())
+                            .comment(r),
                     );
-                    return Ok(true);
+                    return Ok(false);
+                }
+                let refinement = verifier
+                    .refine(&self.config.problem, &final_proof, &r)
+                    .instrument(span)
+                    .await?;
+                if let Some(n_proof) = extract_component(&refinement.raw, "proof") {
+                    final_proof = n_proof;
+                }
+                // A candidate reviewed for another statement says nothing about the problem
+                if refined_statement(&refinement.raw).is_none() {
+                    pending = refinement.review;
                 }
+            } else {
+                self.pending_proof = None;
+                let memid = self.update_memory(
+                    MemoryBlock::new()
+                        .memtype(MemType::Theorem)
+                        .content(&self.config.problem)
+                        .proof(&final_proof)
+                        .deps(final_deps.clone())
+                        .solved(true)
+                        .reviews(used_reviews),
+                );
+                self.memory.mark_verified(memid);
+                self.memory_updated(memid);
+                self.write_checkpoint();
+                self.emit(SessionEvent::TheoremVerified { memid });
+                return Ok(true);
             }
-        } else {
-            return Ok(false);
         }
-
-        return Ok(false);
+        self.pending_proof = None;
+        self.write_checkpoint();
+        Ok(false)
     }
-}
-
-#[async_trait::async_trait]
-impl Session for ResearchSession {
-    async fn run(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if !self.config.logdir.exists() {
-            panic!("Project path {:?} does not exist!", &self.config.logdir);
-        }
-        info!(
-            "Starting ResearchSession from logdir: {:?}",
-            &self.config.logdir
-        );
-
-        let problem_path = self.config.logdir.as_path().join("problem.md");
-        let problem = fs::read_to_string(problem_path)?;
-        self.config.set_problem(&problem);
-        self.config.save_configs()?;
 
+    /// Workflow of `run` once the problem is loaded: resume or load context, then explore
+    async fn explore_problem(
+        &mut self,
+        problem: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         info!("Loaded problem: {:?}", &problem);
         self.explorer.set_problem(problem);
 
+        let cancel = self.cancel.clone();
+        let mut outcome = Outcome::Unresolved;
         if self.config.resume {
             info!("Resuming from previous explorations");
-            self.resume()?;
-            // If a theorem is already found in memory, aim will not continue exploring.
-            for mem in &self.memory.memory {
-                if mem.memtype == "theorem" {
-                    info!("A final theorem already exists in history memory.");
+            if let Some(usage) = self.resume()? {
+                self.telemetry.restore(usage);
+            }
+            // If a verified theorem is already found in memory, aim will not continue exploring.
+            let resumed = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => Ok(Outcome::Interrupted),
+                proved = self.resume_theorems() => proved.map(|proved| match proved {
+                    true => Outcome::Proved,
+                    false => Outcome::Unresolved,
+                }),
+            };
+            match self.step_outcome(resumed)? {
+                Outcome::Proved => {
+                    self.write_checkpoint();
+                    info!("A verified final theorem exists in history memory.");
                     info!("Start reformatting memory and canceling exploration.");
                     self.format_to_markdown().await?;
+                    self.write_report(Outcome::Proved)?;
+                    self.write_manifest(Outcome::Proved.status(), true);
                     return Ok(());
                 }
+                resumed => outcome = resumed,
             }
         } else {
-            self.load_context().await?;
+            tokio::select! {
+                biased;
+                _ = cancel.cancelled() => outcome = Outcome::Interrupted,
+                loaded = self.load_context() => loaded?,
+            }
         }
 
         let pb =
@@ -875,25 +2779,122 @@ impl Session for ResearchSession {
                 "{msg} [{elapsed_precise}] {wide_bar} {pos}/{len} (eta: {eta})",
             )?);
         pb.set_message("Exploring");
-        for i in 0..self.config.steps {
+        if self.config.disproof {
+            self.start_disproof_track()?;
+        }
+        let mut first_step = self.config.currect_steps;
+        if outcome != Outcome::Unresolved {
+            self.write_checkpoints();
+        } else if let Some(pending) = self.pending_proof.take() {
+            // The pending proof completes the step that was interrupted
+            info!("Resuming the verification of a pending final proof");
+            let proved = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => Ok(Outcome::Interrupted),
+                proved = self.verify_final_proof(pending) => proved.map(|proved| match proved {
+                    true => Outcome::Proved,
+                    false => Outcome::Unresolved,
+                }),
+            };
+            outcome = self.step_outcome(proved)?;
+            if outcome.completes_step() {
+                first_step += 1;
+                self.config.set_current_steps(first_step);
+            }
+            self.write_checkpoints();
+        }
+        pb.set_position(first_step.into());
+        for i in first_step..self.config.steps {
+            if outcome != Outcome::Unresolved {
+                break;
+            }
+            if let Some(reason) = self.budget_exhausted() {
+                warn!(
+                    "Stopping the session since its budget is exhausted: {}",
+                    reason
+                );
+                outcome = Outcome::BudgetExhausted;
+                break;
+            }
             info!("Starting Exploration Step: {}", i);
             self.config.set_current_steps(i);
-            let solved = if self.config.theorem_graph_mode {
-                self.graph_step().await?
-            } else {
-                self.step().await?
+            self.emit(SessionEvent::StepStarted { step: i });
+            // Dropping the step on interruption aborts its agent calls, everything it already
+            // added to memory is written to the checkpoint below
+            outcome = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => Outcome::Interrupted,
+                outcome = self
+                    .explore_tracks()
+                    .instrument(info_span!("session_step", step = i)) => {
+                    match self.step_outcome(outcome) {
+                        Ok(outcome) => outcome,
+                        Err(e) => {
+                            self.write_checkpoints();
+                            return Err(e);
+                        }
+                    }
+                },
             };
+            if outcome.completes_step() {
+                self.config.set_current_steps(i + 1);
+            }
+            self.write_checkpoints();
             self.save_memory().await?;
+            if let Some(track) = &self.disproof {
+                track.save_memory().await?;
+            }
             pb.inc(1);
-            if solved {
+            if outcome != Outcome::Unresolved {
+                info!("The session ended with outcome {:?}", outcome);
                 break;
             }
         }
         self.format_to_markdown().await?;
+        self.write_report(outcome)?;
+        self.write_manifest(outcome.status(), true);
+        self.emit(SessionEvent::Finished {
+            status: outcome.status().to_string(),
+        });
 
         Ok(())
     }
+}
+
+#[async_trait::async_trait]
+impl Session for ResearchSession {
+    #[tracing::instrument(name = "session", skip_all, fields(project = %self.config.logdir.display()))]
+    async fn run(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.config.logdir.exists() {
+            panic!("Project path {:?} does not exist!", &self.config.logdir);
+        }
+        info!(
+            "Starting ResearchSession from logdir: {:?}",
+            &self.config.logdir
+        );
+
+        let problem_path = self.config.logdir.as_path().join("problem.md");
+        let problem = fs::read_to_string(problem_path)?;
+        self.config.set_problem(&problem);
+        self.config.save_configs()?;
+        self.write_manifest("running", false);
+
+        // Errors leave the session unfinished, the manifest records that it failed
+        let result = self.explore_problem(problem).await;
+        if let Err(e) = &result {
+            self.write_manifest("failed", true);
+            self.emit(SessionEvent::Error {
+                message: e.to_string(),
+            });
+        }
+        result
+    }
     /// Run session remotely: same workflow as `run`, but persist results in SQLite
+    #[tracing::instrument(
+        name = "session",
+        skip(self, db),
+        fields(project_id = tracing::field::Empty)
+    )]
     async fn remote_run(
         &mut self,
         db: &DatabaseConnection,
@@ -907,7 +2908,7 @@ impl Session for ResearchSession {
             .memory
             .memory
             .iter()
-            .find(|m| m.memtype == "context")
+            .find(|m| m.memtype == MemType::Context)
             .map(|m| m.content.clone());
 
         let need_gen = existing_ctx.is_none();
@@ -924,11 +2925,11 @@ impl Session for ResearchSession {
             .memory
             .memory
             .iter()
-            .filter(|m| m.memtype == "lemma")
+            .filter(|m| m.memtype == MemType::Lemma)
             .count() as i32;
 
         let insert_sql = format!(
-            "INSERT INTO projects (user_id, title, problem, context, config, memory, created_at, last_active, lemmas_count, status) VALUES ({}, '{}', '{}', '{}', '{}', '{}', '{}', '{}', {}, 'running')",
+            "INSERT INTO projects (user_id, title, problem, context, config, memory, created_at, last_active, lemmas_count, status, event_log) VALUES ({}, '{}', '{}', '{}', '{}', '{}', '{}', '{}', {}, 'running', '{}')",
             user_id,
             title,
             self.config.problem.replace("'", "''"),
@@ -938,9 +2939,32 @@ impl Session for ResearchSession {
             ts,
             ts,
             init_lemmas,
+            self.config.event_log.replace("'", "''"),
         );
-        db.execute(Statement::from_string(DbBackend::Sqlite, insert_sql))
-            .await?;
+        let project_id = db
+            .execute(Statement::from_string(DbBackend::Sqlite, insert_sql))
+            .await?
+            .last_insert_id();
+        tracing::Span::current().record("project_id", project_id);
+        // The log is named by the project, so it is opened once the project has its id
+        if self.config.event_log.is_empty() && !self.config.project_logs.is_empty() {
+            let path = Path::new(&self.config.project_logs).join(format!("{}.jsonl", project_id));
+            if let Some(log) = Self::create_event_log(&path) {
+                self.telemetry.attach_log(log.clone());
+                self.add_observer(log);
+                self.config.event_log = path.to_string_lossy().into_owned();
+                let log_sql = format!(
+                    "UPDATE projects SET event_log='{}', config='{}' WHERE id={}",
+                    self.config.event_log.replace("'", "''"),
+                    serde_json::to_string(&self.config)?.replace("'", "''"),
+                    project_id
+                );
+                db.execute(Statement::from_string(DbBackend::Sqlite, log_sql))
+                    .await?;
+            }
+        }
+        self.emit(SessionEvent::ProjectCreated { project_id });
+        self.write_manifest("running", false);
 
         let project_filter = format!("user_id={} AND created_at='{}'", user_id, ts);
 
@@ -948,13 +2972,14 @@ impl Session for ResearchSession {
         if need_gen {
             info!("No context provided. Generating context via LLM for remote session...");
             let generator = ContextGenerator::new()
+                .telemetry(self.telemetry.clone())
                 .model(&self.config.proof_model)
                 .problem(&self.config.problem)
                 .reasoning_effort(self.config.reasoning_effort.clone());
             let context = generator._process().await?;
             self.memory.update(
                 MemoryBlock::new()
-                    .memtype("context")
+                    .memtype(MemType::Context)
                     .content(&context)
                     .solved(true),
             );
@@ -973,19 +2998,29 @@ impl Session for ResearchSession {
 
         let run_result = self.drive_remote_pipeline(db, &project_filter).await;
         match run_result {
-            Ok(solved_flag) => {
-                let status = if solved_flag { "solved" } else { "ended" };
+            Ok(outcome) => {
                 let now = Utc::now().to_rfc3339().replace("'", "''");
                 let status_sql = format!(
-                    "UPDATE projects SET status='{}', error='', last_active='{}' WHERE {}",
-                    status, now, project_filter
+                    "UPDATE projects SET status='{}', report='{}', error='', last_active='{}' WHERE {}",
+                    outcome.status(),
+                    self.report(outcome).replace("'", "''"),
+                    now,
+                    project_filter
                 );
                 db.execute(Statement::from_string(DbBackend::Sqlite, status_sql))
                     .await?;
+                self.write_manifest(outcome.status(), true);
+                self.emit(SessionEvent::Finished {
+                    status: outcome.status().to_string(),
+                });
                 Ok(())
             }
             Err(e) => {
                 let err_msg = e.to_string();
+                self.write_manifest("failed", true);
+                self.emit(SessionEvent::Error {
+                    message: err_msg.clone(),
+                });
                 let trimmed = err_msg.trim();
                 let truncated: String = trimmed.chars().take(512).collect();
                 let sanitized = truncated.replace("'", "''");
@@ -1005,3 +3040,102 @@ impl Session for ResearchSession {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::Usage;
+    use crate::utils::offline_env;
+
+    fn exhausted_session(reviewer: &str) -> ResearchSession {
+        // The only call of the budget is used up, so every agent call fails without a request
+        offline_env();
+        let mut config = ResearchSessionConfig::new()
+            .reviewer(reviewer)
+            .reviews(2)
+            .iterations(3)
+            .call_budget(1);
+        config.set_problem("Every even number greater than 2 is a sum of two primes.");
+        let session = ResearchSession::new(config);
+        session.telemetry.restore(Usage {
+            calls: 1,
+            ..Default::default()
+        });
+        session
+    }
+
+    #[tokio::test]
+    async fn final_proof_is_not_accepted_when_the_budget_runs_out() {
+        for reviewer in ["pessimistic", "progressive"] {
+            let mut session = exhausted_session(reviewer);
+            let pending = PendingProof {
+                proof: "Obvious.".into(),
+                deps: Vec::new(),
+                iteration: 0,
+            };
+            let result = session
+                .verify_final_proof(pending)
+                .await
+                .map(|proved| match proved {
+                    true => Outcome::Proved,
+                    false => Outcome::Unresolved,
+                });
+            assert!(result.is_err(), "{} reviewer", reviewer);
+            let outcome = session.step_outcome(result).unwrap();
+            assert_eq!(outcome, Outcome::BudgetExhausted);
+            assert!(!outcome.completes_step());
+            assert!(
+                !session
+                    .memory
+                    .memory
+                    .iter()
+                    .any(|m| m.memtype == MemType::Theorem),
+                "{} reviewer",
+                reviewer
+            );
+            // The proof stays pending, a resumed session with more budget reviews it
+            assert!(session.pending_proof.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn proof_path_is_not_verified_when_the_budget_runs_out() {
+        for reviewer in ["pessimistic", "progressive"] {
+            let mut session = exhausted_session(reviewer);
+            let memid = session.memory.update(
+                MemoryBlock::new()
+                    .memtype(MemType::Lemma)
+                    .content("Every prime greater than 2 is odd.")
+                    .proof("An even number greater than 2 is divisible by 2.")
+                    .solved(true),
+            );
+            assert!(session.backtrace_review_from(memid).await.is_err());
+            assert!(!session.memory.is_verified(memid), "{} reviewer", reviewer);
+        }
+    }
+
+    #[test]
+    fn memory_changes_are_checkpointed_mid_step() {
+        offline_env();
+        let logdir = std::env::temp_dir().join(format!("aim-checkpoint-{}", std::process::id()));
+        fs::create_dir_all(&logdir).unwrap();
+        let mut config = ResearchSessionConfig::new().logdir(logdir.to_string_lossy());
+        config.set_problem("Every even number greater than 2 is a sum of two primes.");
+        let mut session = ResearchSession::new(config);
+        let memid = session.update_memory(
+            MemoryBlock::new()
+                .memtype(MemType::Theorem)
+                .content("Every even number greater than 2 is a sum of two primes.")
+                .proof("Obvious.")
+                .solved(true),
+        );
+        let json = fs::read_to_string(logdir.join("checkpoint.json")).unwrap();
+        let checkpoint: Checkpoint<Memory> = serde_json::from_str(&json).unwrap();
+        fs::remove_dir_all(&logdir).unwrap();
+        assert!(checkpoint.pending_proof.is_none());
+        assert_eq!(
+            checkpoint.memory.get(memid).unwrap().memtype,
+            MemType::Theorem
+        );
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use crate::agents::{MemType, Memory, MemoryId};
+use crate::sessions::ResearchSession;
+use crate::utils::normalize_text;
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+// Exploration bonus of the UCB rule used by the best-first strategy
+const EXPLORATION_WEIGHT: f64 = 0.7;
+// Steps spent on each auxiliary variant before moving on
+const STEPS_PER_VARIANT: u32 = 3;
+
+/// Decides how each exploration step of a research session is carried out
+#[async_trait::async_trait]
+pub trait ExplorationStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Run one exploration step, returning true once the problem is solved
+    async fn step(
+        &mut self,
+        session: &mut ResearchSession,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+    /// State kept in the checkpoint so that a resumed session continues the same way
+    fn state(&self) -> Option<serde_json::Value> {
+        None
+    }
+    /// Restore the state kept in the checkpoint of an earlier run
+    fn restore(&mut self, _state: serde_json::Value) {}
+}
+
+/// Build the strategy with the given name, one of "linear", "graph", "best_first", "planned" or
+/// "variants"
+pub fn build_strategy(name: &str) -> Result<Box<dyn ExplorationStrategy>, String> {
+    match name {
+        "linear" => Ok(Box::new(LinearStrategy)),
+        "graph" => Ok(Box::new(GraphStrategy)),
+        "best_first" => Ok(Box::new(BestFirstStrategy::new())),
+        "planned" => Ok(Box::new(PlannedStrategy::new())),
+        "variants" => Ok(Box::new(VariantStrategy::new())),
+        _ => Err(format!("Unknown exploration strategy: {}", name)),
+    }
+}
+
+/// Explorations verified on the spot, one step after another
+pub struct LinearStrategy;
+
+#[async_trait::async_trait]
+impl ExplorationStrategy for LinearStrategy {
+    fn name(&self) -> &'static str {
+        "linear"
+    }
+    async fn step(
+        &mut self,
+        session: &mut ResearchSession,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        session.step().await
+    }
+}
+
+/// Explorations collected into the theorem graph and reviewed along the final proof path
+pub struct GraphStrategy;
+
+#[async_trait::async_trait]
+impl ExplorationStrategy for GraphStrategy {
+    fn name(&self) -> &'static str {
+        "graph"
+    }
+    async fn step(
+        &mut self,
+        session: &mut ResearchSession,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        session.graph_step().await
+    }
+}
+
+/// Theorem graph exploration that expands the most promising node in each step. A node is
+/// valued by how many of the descendants produced by focusing on it have been verified, with a
+/// UCB bonus for nodes that were rarely expanded. `None` stands for the problem itself without
+/// any focus.
+pub struct BestFirstStrategy {
+    visits: HashMap<Option<MemoryId>, u32>,
+    // Focus of the step that produced each node, credited once the node is verified
+    origins: HashMap<MemoryId, Option<MemoryId>>,
+}
+
+impl BestFirstStrategy {
+    pub fn new() -> Self {
+        BestFirstStrategy {
+            visits: HashMap::new(),
+            origins: HashMap::new(),
+        }
+    }
+
+    fn wins(&self, session: &ResearchSession) -> HashMap<Option<MemoryId>, u32> {
+        // Unverified descendants may still be refuted, so only verified ones count as wins
+        let memory = session.memory();
+        let mut wins: HashMap<Option<MemoryId>, u32> = HashMap::new();
+        for (id, focus) in &self.origins {
+            if memory.is_verified(*id) {
+                *wins.entry(*focus).or_default() += 1;
+            }
+        }
+        wins
+    }
+
+    fn value(&self, node: Option<MemoryId>, wins: u32, total_visits: u32) -> f64 {
+        let visits = self.visits.get(&node).copied().unwrap_or(0) as f64;
+        let wins = wins as f64;
+        let exploitation = (wins + 1.0) / (visits + 2.0);
+        let exploration = ((total_visits as f64 + 1.0).ln() / (visits + 1.0)).sqrt();
+        exploitation + EXPLORATION_WEIGHT * exploration
+    }
+
+    fn select(&self, session: &ResearchSession) -> Option<MemoryId> {
+        // The frontier consists of the problem itself and every accepted lemma or definition
+        let total_visits = self.visits.values().sum();
+        let wins = self.wins(session);
+        let frontier = session
+            .memory()
+            .memory
+            .iter()
+            .filter(|m| m.is_solved() && matches!(m.memtype, MemType::Lemma | MemType::Definition))
+            .map(|m| Some(m.get_id()));
+        std::iter::once(None)
+            .chain(frontier)
+            .map(|node| {
+                let value = self.value(node, wins.get(&node).copied().unwrap_or(0), total_visits);
+                (node, value)
+            })
+            .inspect(|(node, value)| debug!("Frontier node {:?} valued {:.3}", node, value))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .and_then(|(node, _)| node)
+    }
+}
+
+#[async_trait::async_trait]
+impl ExplorationStrategy for BestFirstStrategy {
+    fn name(&self) -> &'static str {
+        "best_first"
+    }
+    fn state(&self) -> Option<serde_json::Value> {
+        // JSON maps need string keys, so the statistics are kept as lists of pairs
+        let visits: Vec<_> = self.visits.iter().collect();
+        let origins: Vec<_> = self.origins.iter().collect();
+        Some(serde_json::json!({"visits": visits, "origins": origins}))
+    }
+    fn restore(&mut self, state: serde_json::Value) {
+        let visits =
+            serde_json::from_value::<Vec<(Option<MemoryId>, u32)>>(state["visits"].clone());
+        let origins =
+            serde_json::from_value::<Vec<(MemoryId, Option<MemoryId>)>>(state["origins"].clone());
+        match (visits, origins) {
+            (Ok(visits), Ok(origins)) => {
+                self.visits = visits.into_iter().collect();
+                self.origins = origins.into_iter().collect();
+            }
+            _ => warn!("Failed to restore the node statistics, starting afresh"),
+        }
+    }
+    async fn step(
+        &mut self,
+        session: &mut ResearchSession,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let focus = self.select(session);
+        match focus {
+            Some(id) => info!("Expanding memory ID {} in this step", id),
+            None => info!("Expanding the original problem in this step"),
+        }
+        let known: Vec<MemoryId> = session.memory().memory.iter().map(|m| m.get_id()).collect();
+        session.set_focus(focus);
+        let result = session.graph_step().await;
+        session.set_focus(None);
+        let solved = result?;
+
+        // New nodes of this step that build on the focus are credited to it once verified
+        let memory = session.memory();
+        let descendants = match focus {
+            Some(id) => memory.get_dependent_ids(id),
+            None => memory.memory.iter().map(|m| m.get_id()).collect(),
+        };
+        for id in descendants {
+            if !known.contains(&id)
+                && memory
+                    .get(id)
+                    .is_some_and(|m| m.is_solved() && m.memtype.is_citable())
+            {
+                self.origins.entry(id).or_insert(focus);
+            }
+        }
+        if focus.is_some_and(|id| memory.get(id).is_none_or(|m| !m.is_solved())) {
+            warn!("Focused memory ID {:?} was refuted in this step", focus);
+        }
+        *self.visits.entry(focus).or_default() += 1;
+        Ok(solved)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SubgoalStatus {
+    Open,
+    Proved,
+    Refuted,
+}
+
+fn subgoal_status(memory: &Memory, id: MemoryId, statement: &str) -> SubgoalStatus {
+    // A subgoal is refuted once the refiner replaced its statement by the opposite one
+    match memory.get(id) {
+        Some(m) if normalize_text(&m.content) != normalize_text(statement) => {
+            SubgoalStatus::Refuted
+        }
+        Some(m) if m.memtype == MemType::Lemma && memory.is_grounded(id) => SubgoalStatus::Proved,
+        Some(_) => SubgoalStatus::Open,
+        None => SubgoalStatus::Refuted,
+    }
+}
+
+/// Theorem graph exploration directed by a proof skeleton. The planner decomposes the problem
+/// into subgoals stored as open questions, each step attacks the first open subgoal, and the
+/// plan is revised as soon as a subgoal is refuted. Once every subgoal is proved, the explorer
+/// turns to the problem itself.
+#[derive(Serialize, Deserialize)]
+pub struct PlannedStrategy {
+    skeleton: String,
+    /// Memory node of each subgoal together with its planned statement
+    subgoals: Vec<(MemoryId, String)>,
+    planned: bool,
+}
+
+impl PlannedStrategy {
+    pub fn new() -> Self {
+        PlannedStrategy {
+            skeleton: String::new(),
+            subgoals: Vec::new(),
+            planned: false,
+        }
+    }
+
+    fn format_plan(&self, memory: &Memory) -> String {
+        let subgoals = self
+            .subgoals
+            .iter()
+            .map(|(id, statement)| {
+                let status = match subgoal_status(memory, *id, statement) {
+                    SubgoalStatus::Open => "open",
+                    SubgoalStatus::Proved => "proved",
+                    SubgoalStatus::Refuted => "refuted",
+                };
+                format!(
+                    "\\begin{{subgoal}}{}\\end{{subgoal}} (memory ID {}, {})",
+                    statement, id, status
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!(
+            "{}\n\n\\begin{{skeleton}}{}\\end{{skeleton}}",
+            subgoals, self.skeleton
+        )
+    }
+
+    async fn revise(
+        &mut self,
+        session: &mut ResearchSession,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let revision = self.planned.then(|| self.format_plan(session.memory()));
+        self.planned = true;
+        let Some((skeleton, statements)) = session.plan(revision).await? else {
+            // Without a new plan, refuted subgoals are dropped so that they are not retried
+            let (refuted, kept) =
+                std::mem::take(&mut self.subgoals)
+                    .into_iter()
+                    .partition(|(id, s)| {
+                        subgoal_status(session.memory(), *id, s) == SubgoalStatus::Refuted
+                    });
+            self.subgoals = kept;
+            for (id, statement) in refuted {
+                session.abandon_subgoal(id, &statement);
+            }
+            session.save_strategy_state(self.state());
+            return Ok(());
+        };
+        let mut subgoals = Vec::new();
+        for statement in statements {
+            // Subgoals restated by the new plan are kept as they are, unless they were refuted
+            let kept = self.subgoals.iter().position(|(id, s)| {
+                normalize_text(s) == normalize_text(&statement)
+                    && subgoal_status(session.memory(), *id, s) != SubgoalStatus::Refuted
+            });
+            match kept {
+                Some(i) => subgoals.push(self.subgoals.remove(i)),
+                None => subgoals.push((session.add_open_question(&statement), statement)),
+            }
+        }
+        // The subgoals left out of the new plan would otherwise linger as open work
+        for (id, statement) in std::mem::take(&mut self.subgoals) {
+            session.abandon_subgoal(id, &statement);
+        }
+        info!(
+            "Planned {} subgoals: {:?}",
+            subgoals.len(),
+            subgoals.iter().map(|(id, _)| id).collect::<Vec<_>>()
+        );
+        self.subgoals = subgoals;
+        self.skeleton = skeleton;
+        session.save_strategy_state(self.state());
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl ExplorationStrategy for PlannedStrategy {
+    fn name(&self) -> &'static str {
+        "planned"
+    }
+    fn state(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+    fn restore(&mut self, state: serde_json::Value) {
+        match serde_json::from_value(state) {
+            Ok(strategy) => *self = strategy,
+            Err(e) => warn!("Failed to restore the plan, planning anew: {}", e),
+        }
+    }
+    async fn step(
+        &mut self,
+        session: &mut ResearchSession,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let refuted = self
+            .subgoals
+            .iter()
+            .any(|(id, s)| subgoal_status(session.memory(), *id, s) == SubgoalStatus::Refuted);
+        if !self.planned || refuted {
+            self.revise(session).await?;
+        }
+        let open = self
+            .subgoals
+            .iter()
+            .find(|(id, s)| subgoal_status(session.memory(), *id, s) == SubgoalStatus::Open)
+            .map(|(id, _)| *id);
+        match open {
+            Some(id) => info!("Exploring the subgoal in memory ID {}", id),
+            None => info!("No open subgoals are left, exploring the problem itself"),
+        }
+        session.set_goal(open);
+        let result = session.graph_step().await;
+        session.set_goal(None);
+        result
+    }
+}
+
+/// Theorem graph exploration that warms up on simpler variants of the problem. The variants
+/// share the memory of the session as open questions, each gets a few steps of its own, and a
+/// variant that is proved stays in memory as a lemma for the original problem.
+#[derive(Serialize, Deserialize)]
+pub struct VariantStrategy {
+    /// Memory node of each variant with its statement and the steps spent on it
+    variants: Vec<(MemoryId, String, u32)>,
+    proposed: bool,
+}
+
+impl VariantStrategy {
+    pub fn new() -> Self {
+        VariantStrategy {
+            variants: Vec::new(),
+            proposed: false,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ExplorationStrategy for VariantStrategy {
+    fn name(&self) -> &'static str {
+        "variants"
+    }
+    fn state(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+    fn restore(&mut self, state: serde_json::Value) {
+        match serde_json::from_value(state) {
+            Ok(strategy) => *self = strategy,
+            Err(e) => warn!("Failed to restore the variants, proposing them anew: {}", e),
+        }
+    }
+    async fn step(
+        &mut self,
+        session: &mut ResearchSession,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        if !self.proposed {
+            self.proposed = true;
+            // Without variants the strategy simply explores the problem itself
+            let variants = session.propose_variants().await.unwrap_or_else(|e| {
+                warn!("Failed to propose variants of the problem: {}", e);
+                Vec::new()
+            });
+            for statement in variants {
+                let id = session.add_open_question(&statement);
+                self.variants.push((id, statement, 0));
+            }
+            info!(
+                "Exploring {} variants of the problem first",
+                self.variants.len()
+            );
+        }
+        let memory = session.memory();
+        let current = self.variants.iter_mut().find(|(id, statement, steps)| {
+            *steps < STEPS_PER_VARIANT
+                && subgoal_status(memory, *id, statement) == SubgoalStatus::Open
+        });
+        let Some((id, _, steps)) = current else {
+            return session.graph_step().await;
+        };
+        let id = *id;
+        *steps += 1;
+        info!("Exploring the variant in memory ID {} (step {})", id, steps);
+        session.set_goal(Some(id));
+        let result = session.graph_step().await;
+        session.set_goal(None);
+        let memory = session.memory();
+        if let Some((_, statement, _)) = self.variants.iter().find(|(v, ..)| *v == id)
+            && subgoal_status(memory, id, statement) == SubgoalStatus::Proved
+        {
+            info!(
+                "The variant in memory ID {} is proved and promoted to a lemma",
+                id
+            );
+        }
+        result
+    }
+}
@@ -0,0 +1,82 @@
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, fmt};
+
+/// Flushes the exported spans when dropped at the end of `main`
+pub struct TracingGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take()
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("Failed to flush the OTLP spans: {}", e);
+        }
+    }
+}
+
+/// Install the global subscriber, which also collects the records of the `log` macros.
+///
+/// `format` is one of "pretty", "json" or "otlp"; "otlp" prints like "pretty" and exports the
+/// spans over OTLP/HTTP to `otlp_endpoint`. In server mode agents & sessions are silenced to
+/// errors on the output, while their spans are still exported.
+pub fn init(
+    format: &str,
+    otlp_endpoint: &str,
+    server: bool,
+) -> Result<TracingGuard, Box<dyn std::error::Error + Send + Sync>> {
+    // Every layer gets its own filter, since an EnvFilter cannot be shared
+    let env_filter =
+        || EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let mut output_filter = env_filter();
+    if server {
+        output_filter = output_filter
+            .add_directive("aim::agents=error".parse()?)
+            .add_directive("aim::sessions=error".parse()?);
+    }
+
+    let output = match format {
+        "json" => fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_writer(std::io::stdout)
+            .with_filter(output_filter)
+            .boxed(),
+        "pretty" | "otlp" => fmt::layer()
+            .with_writer(std::io::stdout)
+            .with_filter(output_filter)
+            .boxed(),
+        _ => return Err(format!("Unknown log format {}", format).into()),
+    };
+
+    let provider = if format == "otlp" {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(otlp_endpoint)
+            .build()?;
+        Some(
+            SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name("aim").build())
+                .build(),
+        )
+    } else {
+        None
+    };
+    let otel = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("aim"))
+            .with_filter(env_filter())
+    });
+
+    tracing_subscriber::registry()
+        .with(output)
+        .with(otel)
+        .try_init()?;
+    Ok(TracingGuard { provider })
+}
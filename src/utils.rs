@@ -1,6 +1,98 @@
 use log::warn;
 use regex::Regex;
 use regex::escape as regex_escape;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    // Write to a temporary file next to the target and rename it over the target, so that a
+    // crash leaves either the old or the new file but never a truncated one
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::other(format!("Invalid file path: {:?}", path)))?;
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+pub fn content_hash(parts: &[&str]) -> String {
+    // Stable 64-bit FNV-1a hash of the given parts, rendered as hex. Unlike the std hasher its
+    // value never changes across builds, so it can be persisted in memory files.
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+    let mut hash = FNV_OFFSET;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0xff)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+pub fn normalize_text(text: &str) -> String {
+    // Lowercase the text and collapse all whitespace, so that trivially different restatements
+    // compare equal
+    text.split_whitespace()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn canonical_text(text: &str) -> String {
+    // Lowercase the text and drop whitespace and inline math delimiters, keeping operators and
+    // other symbols that change the meaning of a statement
+    text.chars()
+        .filter(|c| !c.is_whitespace() && *c != '$')
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+pub fn text_similarity(a: &str, b: &str) -> f64 {
+    // Jaccard similarity of the character trigrams of both canonical texts
+    fn trigrams(text: &str) -> HashSet<[char; 3]> {
+        let chars: Vec<char> = canonical_text(text).chars().collect();
+        chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+    }
+    let (ta, tb) = (trigrams(a), trigrams(b));
+    if ta.is_empty() || tb.is_empty() {
+        return if canonical_text(a) == canonical_text(b) {
+            1.0
+        } else {
+            0.0
+        };
+    }
+    let common = ta.intersection(&tb).count();
+    common as f64 / (ta.len() + tb.len() - common) as f64
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum();
+    let na: f64 = a.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    let nb: f64 = b.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    if na == 0.0 || nb == 0.0 {
+        0.0
+    } else {
+        dot / (na * nb)
+    }
+}
+
+pub fn estimate_tokens(text: &str) -> usize {
+    // Rough token count of a text, assuming about four characters per token
+    text.chars().count().div_ceil(4)
+}
+
+pub fn truncate_chars(text: &str, max_chars: usize) -> String {
+    // Truncate the text to at most `max_chars` characters, marking the truncation
+    match text.char_indices().nth(max_chars) {
+        Some((pos, _)) => format!("{} [...]", &text[..pos]),
+        None => text.to_string(),
+    }
+}
 
 pub fn find_box(pred_str: &str) -> Option<String> {
     let after_boxed = match pred_str.rfind("boxed") {
@@ -84,3 +176,52 @@ pub fn extract_all_component(text: &str, tag: &str) -> Vec<String> {
     }
     contents
 }
+
+/// Lets tests build agent clients without a `.env` file, they never reach the API
+#[cfg(test)]
+pub fn offline_env() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    // SAFETY: the std environment functions are synchronized with each other
+    INIT.call_once(|| unsafe { std::env::set_var("OPENAI_API_KEY", "test") });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_text_ignores_whitespace_case_and_inline_math() {
+        assert_eq!(
+            canonical_text("For all  $n \\geq 1$,\n $a_n$ is Even."),
+            canonical_text("for all n\\geq1, a_n is even.")
+        );
+    }
+
+    #[test]
+    fn canonical_text_keeps_operators() {
+        assert_ne!(canonical_text("$x < y$"), canonical_text("$x > y$"));
+        assert_ne!(canonical_text("$a - b$"), canonical_text("$a + b$"));
+        assert_ne!(canonical_text("$x \\leq y$"), canonical_text("$x \\geq y$"));
+    }
+
+    #[test]
+    fn text_similarity_of_restatements_is_one() {
+        assert_eq!(
+            text_similarity("Let $G$ be a finite group.", "let G be a  finite\ngroup."),
+            1.0
+        );
+        assert_eq!(text_similarity("ab", "$a b$"), 1.0);
+    }
+
+    #[test]
+    fn text_similarity_of_near_misses_is_below_one() {
+        let statement =
+            "For every integer $n \\geq 1$, the sum $1 + \\dots + n$ equals $n(n+1)/2$.";
+        let near_miss =
+            "For every integer $n \\geq 2$, the sum $1 + \\dots + n$ equals $n(n+1)/2$.";
+        let score = text_similarity(statement, near_miss);
+        assert!(score > 0.5 && score < 1.0, "{}", score);
+        assert!(text_similarity(statement, "Every finite group of prime order is cyclic.") < 0.2);
+        assert_eq!(text_similarity("ab", "ba"), 0.0);
+    }
+}